@@ -1,5 +1,5 @@
 use android_activity::{
-    input::{InputEvent, KeyAction, KeyEvent, KeyMapChar, MotionAction},
+    input::{InputEvent, KeyAction, KeyEvent, KeyMapChar, Keycode, MotionAction},
     AndroidApp, InputStatus, MainEvent, PollEvent,
 };
 use log::info;
@@ -54,6 +54,8 @@ struct App {
     pointer: Option<Pointer>,
     num_pointers: usize,
     last_touch: Option<f32>,
+    // distance between two fingers
+    pinch: Option<f32>,
 }
 impl App {
     fn new() -> Self {
@@ -62,6 +64,7 @@ impl App {
             pointer: None,
             num_pointers: 0,
             last_touch: None,
+            pinch: None,
         }
     }
     fn init_window(&mut self, native_window: ndk::native_window::NativeWindow) {
@@ -87,7 +90,7 @@ impl App {
     }
     fn gyro(&mut self, gyro: [f32; 3]) {
         if let Some(rm) = &mut self.ray_marcher {
-            if self.pointer.is_none() {
+            if self.pointer.is_none() && rm.orbit.is_none() {
                 let v = rm.camera.rot.transform_vector(
                     &(raymarcher::na::Vector3::new(-gyro[0], -gyro[1], gyro[2]) * GYRO_PERIOD),
                 );
@@ -100,33 +103,24 @@ impl App {
     fn update(&mut self, dt: f32) {
         if let Some(rm) = &mut self.ray_marcher {
             if let Some(pointer) = &mut self.pointer {
-                let sensativity = 0.001;
                 let d = pointer.pre - pointer.now;
 
-                let v = rm
-                    .camera
-                    .rot
-                    .transform_vector(&(raymarcher::na::Vector3::new(d.y, d.x, 0.0) * sensativity));
-                rm.camera.rot = rm.camera.rot.append_axisangle_linearized(&v);
+                if let Some(orbit) = &mut rm.orbit {
+                    let sensativity = 0.003;
+                    orbit.rotate(d.x * sensativity, d.y * sensativity);
+                } else {
+                    let sensativity = 0.001;
+                    rm.camera.look(d * sensativity);
+                }
 
                 pointer.update();
-            } else {
-                if self.num_pointers == 2 || self.num_pointers == 3 {
-                    let speed = 0.5;
-                    let v = rm
-                        .camera
-                        .rotation()
-                        .to_homogeneous()
-                        .transform_vector(&raymarcher::na::Vector3::z_axis());
-                    let d = v * speed * dt;
-                    if self.num_pointers == 2 {
-                        rm.camera.pos += d;
-                    }
-                    if self.num_pointers == 3 {
-                        rm.camera.pos -= d;
-                    }
-                }
             }
+            let moving = self.pointer.is_none() && rm.orbit.is_none();
+            rm.controller.translation.z = match self.num_pointers {
+                2 if moving => 1.0,
+                3 if moving => -1.0,
+                _ => 0.0,
+            };
 
             rm.update(dt);
         } else {
@@ -136,6 +130,16 @@ impl App {
             *last_touch += dt;
         }
     }
+    fn pinch(&mut self, distance: f32) {
+        if let Some(rm) = &mut self.ray_marcher {
+            if let (Some(orbit), Some(pre)) = (&mut rm.orbit, self.pinch) {
+                orbit.zoom((distance / pre).ln());
+            }
+        } else {
+            log::error!("Attempted pinch when no window exists!");
+        }
+        self.pinch = Some(distance);
+    }
     fn render(&mut self) {
         if let Some(rm) = &mut self.ray_marcher {
             match rm.render() {
@@ -236,9 +240,19 @@ fn android_main(app: AndroidApp) {
         match app.input_events_iter() {
             Ok(mut iter) => loop {
                 if !iter.next(|event| {
+                    let mut status = InputStatus::Unhandled;
                     match event {
                         InputEvent::KeyEvent(key_event) => {
                             info!("key action = {:?}", key_event.action());
+                            // volume up switches between flying and orbiting
+                            if key_event.key_code() == Keycode::VolumeUp {
+                                if key_event.action() == KeyAction::Up {
+                                    if let Some(rm) = &mut rm_app.ray_marcher {
+                                        rm.toggle_orbit();
+                                    }
+                                }
+                                status = InputStatus::Handled;
+                            }
                             let combined_key_char = character_map_and_combine_key(
                                 &app,
                                 key_event,
@@ -255,6 +269,7 @@ fn android_main(app: AndroidApp) {
                                 | MotionAction::PointerUp
                                 | MotionAction::Cancel => {
                                     rm_app.pointer = None;
+                                    rm_app.pinch = None;
                                     rm_app.num_pointers -= 1;
                                 }
                                 _ => {
@@ -302,6 +317,17 @@ fn android_main(app: AndroidApp) {
                                     } else {
                                         rm_app.pointer = None;
                                         rm_app.last_touch = None;
+                                        if rm_app.num_pointers == 2 {
+                                            let a = motion_event.pointer_at_index(0);
+                                            let b = motion_event.pointer_at_index(1);
+                                            let d = raymarcher::na::Vector2::new(
+                                                a.x() - b.x(),
+                                                a.y() - b.y(),
+                                            );
+                                            rm_app.pinch(d.magnitude());
+                                        } else {
+                                            rm_app.pinch = None;
+                                        }
                                     }
                                 }
                             }
@@ -312,7 +338,7 @@ fn android_main(app: AndroidApp) {
                         _ => {}
                     }
 
-                    InputStatus::Unhandled
+                    status
                 }) {
                     break;
                 }
@@ -0,0 +1,386 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::{Deserialize, Serialize};
+use winit::event::{KeyboardInput, MouseButton, VirtualKeyCode};
+
+use raymarcher::{na, Controller};
+
+/// Everything that can be bound to an input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Action {
+    MoveForwards,
+    MoveBackwards,
+    MoveLeft,
+    MoveRight,
+    MoveUp,
+    MoveDown,
+    LookLeft,
+    LookRight,
+    LookUp,
+    LookDown,
+    Faster,
+    Slower,
+    /// Held to look around with the mouse.
+    Look,
+    SwitchScene,
+    Screenshot,
+    ToggleUi,
+    Autofocus,
+    Pause,
+    ToggleCollision,
+    RecordKeyframe,
+    ClearPath,
+    PlayPath,
+    /// Starts recording input, or stops and saves it.
+    Record,
+    Replay,
+    ToggleOrbit,
+    CycleStereo,
+    /// Bakes the scene as it is into a distance cache, or drops the cache.
+    ToggleDistanceCache,
+    /// Restores the numbered bookmark, or stores it while ctrl is held.
+    Bookmark(u8),
+    Quit,
+}
+impl Action {
+    /// Whether the action lasts as long as its input is held instead of happening once.
+    pub fn is_held(self) -> bool {
+        matches!(
+            self,
+            Action::MoveForwards
+                | Action::MoveBackwards
+                | Action::MoveLeft
+                | Action::MoveRight
+                | Action::MoveUp
+                | Action::MoveDown
+                | Action::LookLeft
+                | Action::LookRight
+                | Action::LookUp
+                | Action::LookDown
+                | Action::Faster
+                | Action::Slower
+                | Action::Look
+        )
+    }
+}
+
+/// The analog inputs of the `Controller`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Axis {
+    MoveRight,
+    MoveUp,
+    MoveForwards,
+    LookRight,
+    LookDown,
+    Speed,
+}
+impl Axis {
+    /// The held actions that push the axis towards -1 and 1.
+    fn actions(self) -> (Action, Action) {
+        match self {
+            Axis::MoveRight => (Action::MoveLeft, Action::MoveRight),
+            Axis::MoveUp => (Action::MoveDown, Action::MoveUp),
+            Axis::MoveForwards => (Action::MoveBackwards, Action::MoveForwards),
+            Axis::LookRight => (Action::LookLeft, Action::LookRight),
+            Axis::LookDown => (Action::LookUp, Action::LookDown),
+            Axis::Speed => (Action::Slower, Action::Faster),
+        }
+    }
+}
+
+/// Gamepad buttons by position, named the same as in gilrs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum GamepadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftTrigger,
+    LeftTrigger2,
+    RightTrigger,
+    RightTrigger2,
+    Select,
+    Start,
+    Mode,
+    LeftThumb,
+    RightThumb,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+#[cfg(feature = "gamepad")]
+impl GamepadButton {
+    pub fn from_gilrs(button: gilrs::Button) -> Option<Self> {
+        use gilrs::Button;
+        Some(match button {
+            Button::South => GamepadButton::South,
+            Button::East => GamepadButton::East,
+            Button::North => GamepadButton::North,
+            Button::West => GamepadButton::West,
+            Button::LeftTrigger => GamepadButton::LeftTrigger,
+            Button::LeftTrigger2 => GamepadButton::LeftTrigger2,
+            Button::RightTrigger => GamepadButton::RightTrigger,
+            Button::RightTrigger2 => GamepadButton::RightTrigger2,
+            Button::Select => GamepadButton::Select,
+            Button::Start => GamepadButton::Start,
+            Button::Mode => GamepadButton::Mode,
+            Button::LeftThumb => GamepadButton::LeftThumb,
+            Button::RightThumb => GamepadButton::RightThumb,
+            Button::DPadUp => GamepadButton::DPadUp,
+            Button::DPadDown => GamepadButton::DPadDown,
+            Button::DPadLeft => GamepadButton::DPadLeft,
+            Button::DPadRight => GamepadButton::DPadRight,
+            _ => return None,
+        })
+    }
+}
+
+/// Gamepad sticks go from -1 to 1 with up being positive, triggers from 0 to 1.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger2,
+    RightTrigger2,
+}
+#[cfg(feature = "gamepad")]
+impl GamepadAxis {
+    pub fn from_gilrs(axis: gilrs::Axis) -> Option<Self> {
+        use gilrs::Axis;
+        Some(match axis {
+            Axis::LeftStickX => GamepadAxis::LeftStickX,
+            Axis::LeftStickY => GamepadAxis::LeftStickY,
+            Axis::RightStickX => GamepadAxis::RightStickX,
+            Axis::RightStickY => GamepadAxis::RightStickY,
+            _ => return None,
+        })
+    }
+    /// Analog triggers are reported as buttons with a value.
+    pub fn from_gilrs_button(button: gilrs::Button) -> Option<Self> {
+        use gilrs::Button;
+        Some(match button {
+            Button::LeftTrigger2 => GamepadAxis::LeftTrigger2,
+            Button::RightTrigger2 => GamepadAxis::RightTrigger2,
+            _ => return None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AxisBinding {
+    pub axis: GamepadAxis,
+    /// Multiplies the gamepad's value, negative to invert it.
+    pub scale: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Binding {
+    /// A key by what it types, so it follows the keyboard layout.
+    Key(VirtualKeyCode),
+    /// A key by where it is on the keyboard.
+    ScanCode(u32),
+    Mouse(MouseButton),
+    Gamepad(GamepadButton),
+}
+impl Binding {
+    /// Both ways a key event can be bound.
+    pub fn keyboard(input: &KeyboardInput) -> impl Iterator<Item = Binding> {
+        input
+            .virtual_keycode
+            .map(Binding::Key)
+            .into_iter()
+            .chain(std::iter::once(Binding::ScanCode(input.scancode)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct InputMap {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+    axes: BTreeMap<Axis, Vec<AxisBinding>>,
+}
+impl Default for InputMap {
+    fn default() -> Self {
+        use Binding::*;
+        use VirtualKeyCode as Key;
+
+        let mut bindings = BTreeMap::from([
+            (
+                Action::MoveForwards,
+                vec![Key(Key::W), Gamepad(GamepadButton::DPadUp)],
+            ),
+            (
+                Action::MoveBackwards,
+                vec![Key(Key::S), Gamepad(GamepadButton::DPadDown)],
+            ),
+            (
+                Action::MoveLeft,
+                vec![Key(Key::A), Gamepad(GamepadButton::DPadLeft)],
+            ),
+            (
+                Action::MoveRight,
+                vec![Key(Key::D), Gamepad(GamepadButton::DPadRight)],
+            ),
+            (Action::MoveUp, vec![Key(Key::Q)]),
+            (Action::MoveDown, vec![Key(Key::E)]),
+            (Action::LookLeft, vec![Key(Key::Left)]),
+            (Action::LookRight, vec![Key(Key::Right)]),
+            (Action::LookUp, vec![Key(Key::Up)]),
+            (Action::LookDown, vec![Key(Key::Down)]),
+            (
+                Action::Faster,
+                vec![Key(Key::LShift), Gamepad(GamepadButton::RightTrigger)],
+            ),
+            (
+                Action::Slower,
+                vec![Key(Key::LAlt), Gamepad(GamepadButton::LeftTrigger)],
+            ),
+            (Action::Look, vec![Mouse(MouseButton::Left)]),
+            (
+                Action::SwitchScene,
+                vec![Key(Key::Space), Gamepad(GamepadButton::North)],
+            ),
+            (Action::Screenshot, vec![Key(Key::F12)]),
+            (
+                Action::ToggleUi,
+                vec![Key(Key::F1), Gamepad(GamepadButton::Select)],
+            ),
+            (
+                Action::Autofocus,
+                vec![Key(Key::F), Gamepad(GamepadButton::West)],
+            ),
+            (
+                Action::Pause,
+                vec![Key(Key::P), Gamepad(GamepadButton::Start)],
+            ),
+            (Action::ToggleCollision, vec![Key(Key::C)]),
+            (Action::RecordKeyframe, vec![Key(Key::K)]),
+            (Action::ClearPath, vec![Key(Key::J)]),
+            (Action::PlayPath, vec![Key(Key::L)]),
+            (Action::Record, vec![Key(Key::R)]),
+            (Action::Replay, vec![Key(Key::T)]),
+            (
+                Action::ToggleOrbit,
+                vec![Key(Key::O), Gamepad(GamepadButton::East)],
+            ),
+            (Action::CycleStereo, vec![Key(Key::V)]),
+            (Action::ToggleDistanceCache, vec![Key(Key::B)]),
+            (Action::Quit, vec![Key(Key::Escape)]),
+        ]);
+        let number_keys = [
+            Key::Key1,
+            Key::Key2,
+            Key::Key3,
+            Key::Key4,
+            Key::Key5,
+            Key::Key6,
+            Key::Key7,
+            Key::Key8,
+            Key::Key9,
+        ];
+        for (i, key) in number_keys.into_iter().enumerate() {
+            bindings.insert(Action::Bookmark(i as u8 + 1), vec![Key(key)]);
+        }
+
+        let axis = |axis, scale| AxisBinding { axis, scale };
+        let axes = BTreeMap::from([
+            (Axis::MoveRight, vec![axis(GamepadAxis::LeftStickX, 1.0)]),
+            (Axis::MoveForwards, vec![axis(GamepadAxis::LeftStickY, 1.0)]),
+            (
+                Axis::MoveUp,
+                vec![
+                    axis(GamepadAxis::RightTrigger2, 1.0),
+                    axis(GamepadAxis::LeftTrigger2, -1.0),
+                ],
+            ),
+            (Axis::LookRight, vec![axis(GamepadAxis::RightStickX, 1.0)]),
+            (Axis::LookDown, vec![axis(GamepadAxis::RightStickY, -1.0)]),
+        ]);
+
+        Self { bindings, axes }
+    }
+}
+impl InputMap {
+    /// Actions bound to `binding`.
+    pub fn actions(&self, binding: Binding) -> impl Iterator<Item = Action> + '_ {
+        self.bindings
+            .iter()
+            .filter(move |(_, bindings)| bindings.contains(&binding))
+            .map(|(action, _)| *action)
+    }
+
+    /// Loads bindings on top of the defaults, so the file only needs the actions it changes.
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::read_to_string(path)?;
+        let loaded: Self = ron::from_str(&file)?;
+        let mut input_map = Self::default();
+        input_map.bindings.extend(loaded.bindings);
+        input_map.axes.extend(loaded.axes);
+        Ok(input_map)
+    }
+}
+
+/// What is currently held down, turned into the `Controller`'s axes every frame.
+#[derive(Clone, Debug, Default)]
+pub struct InputState {
+    held: BTreeSet<Action>,
+    gamepad: BTreeMap<GamepadAxis, f32>,
+    // added up until the next frame
+    mouse: na::Vector2<f32>,
+    zoom: f32,
+}
+impl InputState {
+    pub fn set_held(&mut self, action: Action, pressed: bool) {
+        if pressed {
+            self.held.insert(action);
+        } else {
+            self.held.remove(&action);
+        }
+    }
+    #[cfg_attr(not(feature = "gamepad"), allow(dead_code))]
+    pub fn set_gamepad_axis(&mut self, axis: GamepadAxis, value: f32) {
+        self.gamepad.insert(axis, value);
+    }
+    pub fn add_mouse(&mut self, dx: f32, dy: f32) {
+        self.mouse += na::Vector2::new(dx, dy);
+    }
+    pub fn add_zoom(&mut self, amount: f32) {
+        self.zoom += amount;
+    }
+    /// Lets go of everything, for when the window loses focus or the gamepad disconnects.
+    pub fn release(&mut self) {
+        self.held.clear();
+        self.gamepad.clear();
+    }
+
+    fn axis(&self, input_map: &InputMap, axis: Axis) -> f32 {
+        let (negative, positive) = axis.actions();
+        let held = |action| self.held.contains(&action) as i32 as f32;
+        let gamepad: f32 = input_map
+            .axes
+            .get(&axis)
+            .into_iter()
+            .flatten()
+            .map(|binding| self.gamepad.get(&binding.axis).unwrap_or(&0.0) * binding.scale)
+            .sum();
+        (held(positive) - held(negative) + gamepad).clamp(-1.0, 1.0)
+    }
+    pub fn update_controller(&mut self, input_map: &InputMap, controller: &mut Controller) {
+        controller.translation = na::Vector3::new(
+            self.axis(input_map, Axis::MoveRight),
+            self.axis(input_map, Axis::MoveUp),
+            self.axis(input_map, Axis::MoveForwards),
+        );
+        controller.rotation = na::Vector2::new(
+            self.axis(input_map, Axis::LookRight),
+            self.axis(input_map, Axis::LookDown),
+        );
+        controller.speed = self.axis(input_map, Axis::Speed);
+        controller.looking = self.held.contains(&Action::Look);
+        controller.mouse = std::mem::take(&mut self.mouse);
+        controller.zoom = std::mem::take(&mut self.zoom);
+    }
+}
@@ -1,20 +1,137 @@
 use winit::{
-    event::{
-        DeviceEvent, ElementState, Event, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent,
-    },
+    event::{DeviceEvent, ElementState, Event, ModifiersState, MouseScrollDelta, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
-    window::WindowBuilder,
+    window::{Window, WindowBuilder},
 };
 
-use raymarcher::RayMarcher;
+use raymarcher::{bookmark::Bookmarks, recording::Recording, RayMarcher};
 
-const Q: u32 = 16;
-const E: u32 = 18;
-const W: u32 = 17;
-const A: u32 = 30;
-const S: u32 = 31;
-const D: u32 = 32;
-const SPACE: u32 = 57;
+mod input;
+use input::{Action, Binding, InputMap, InputState};
+
+const TITLE: &str = "Ray Marcher";
+const PATH_FILE: &str = "camera_path.ron";
+const KEYFRAME_INTERVAL: f32 = 2.0;
+const BOOKMARKS_FILE: &str = "bookmarks.ron";
+const BINDINGS_FILE: &str = "bindings.ron";
+const RECORDING_FILE: &str = "recording.ron";
+const ENVIRONMENT_FILE: &str = "environment.hdr";
+const HEIGHTMAP_FILE: &str = "heightmap.png";
+const MESH_FILE: &str = "mesh.obj";
+
+// shared by everything the actions touch
+struct App {
+    ray_marcher: RayMarcher<Window>,
+    bookmarks: Bookmarks,
+    input: InputState,
+    modifiers: ModifiersState,
+    // frame rate and position in the title bar
+    show_stats: bool,
+}
+impl App {
+    /// Returns true when the app should quit.
+    fn action(&mut self, action: Action, pressed: bool) -> bool {
+        let ray_marcher = &mut self.ray_marcher;
+        if action.is_held() {
+            self.input.set_held(action, pressed);
+            return false;
+        }
+        if action == Action::Quit {
+            return pressed;
+        }
+        // everything else happens once when released
+        if pressed {
+            return false;
+        }
+        match action {
+            Action::SwitchScene => ray_marcher.switch_scene(),
+            Action::Screenshot => {
+                let time = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .unwrap_or_default();
+                let file = format!("screenshot_{}.png", time.as_secs());
+                match ray_marcher.screenshot().save(&file) {
+                    Ok(()) => log::info!("Saved {file}"),
+                    Err(e) => log::error!("Failed to save {file}: {e}"),
+                }
+            }
+            Action::ToggleUi => {
+                self.show_stats = !self.show_stats;
+                if !self.show_stats {
+                    ray_marcher.wgpu_ctx.window.set_title(TITLE);
+                }
+            }
+            Action::Autofocus => ray_marcher.autofocus(),
+            Action::Pause => ray_marcher.paused = !ray_marcher.paused,
+            Action::ToggleCollision => {
+                ray_marcher.movement.collision = !ray_marcher.movement.collision
+            }
+            Action::RecordKeyframe => {
+                ray_marcher.record_keyframe(KEYFRAME_INTERVAL);
+                if let Err(e) = ray_marcher.path.save(PATH_FILE) {
+                    log::error!("Failed to save {PATH_FILE}: {e}");
+                }
+            }
+            Action::ClearPath => {
+                ray_marcher.stop_path();
+                ray_marcher.path.clear();
+                if let Err(e) = ray_marcher.path.save(PATH_FILE) {
+                    log::error!("Failed to save {PATH_FILE}: {e}");
+                }
+            }
+            Action::PlayPath => {
+                if ray_marcher.is_playing() {
+                    ray_marcher.stop_path();
+                } else {
+                    ray_marcher.play_path();
+                }
+            }
+            Action::Record => {
+                if let Some(recording) = ray_marcher.stop_recording() {
+                    if let Err(e) = recording.save(RECORDING_FILE) {
+                        log::error!("Failed to save {RECORDING_FILE}: {e}");
+                    }
+                } else {
+                    ray_marcher.start_recording();
+                }
+            }
+            Action::Replay => {
+                if ray_marcher.is_replaying() {
+                    ray_marcher.stop_replay();
+                } else {
+                    match Recording::load(RECORDING_FILE) {
+                        Ok(recording) => ray_marcher.replay(recording, None),
+                        Err(e) => log::error!("Failed to load {RECORDING_FILE}: {e}"),
+                    }
+                }
+            }
+            Action::ToggleOrbit => ray_marcher.toggle_orbit(),
+            Action::CycleStereo => ray_marcher.stereo.mode = ray_marcher.stereo.mode.next(),
+            Action::ToggleDistanceCache => {
+                if ray_marcher.distance_cache().is_some() || ray_marcher.is_baking_distance_cache()
+                {
+                    ray_marcher.set_distance_cache(None);
+                } else {
+                    log::info!("Baking the distance cache");
+                    ray_marcher.bake_distance_cache();
+                }
+            }
+            Action::Bookmark(number) => {
+                let name = number.to_string();
+                if self.modifiers.ctrl() {
+                    self.bookmarks.insert(name, ray_marcher.bookmark());
+                    if let Err(e) = self.bookmarks.save(BOOKMARKS_FILE) {
+                        log::error!("Failed to save {BOOKMARKS_FILE}: {e}");
+                    }
+                } else if let Some(bookmark) = self.bookmarks.get(&name) {
+                    ray_marcher.restore(bookmark);
+                }
+            }
+            _ => {}
+        }
+        false
+    }
+}
 
 fn main() {
     env_logger::init();
@@ -24,76 +141,126 @@ fn main() {
 
     let event_loop = EventLoop::new();
     let window = WindowBuilder::new()
-        .with_title("Ray Marcher")
+        .with_title(TITLE)
         .build(&event_loop)
         .unwrap();
 
     let size = window.inner_size();
-    let mut ray_marcher = pollster::block_on(RayMarcher::new(window, size.into(), 1.0 / 1.0));
+    let mut ray_marcher = pollster::block_on(RayMarcher::new(window, size.into(), 1.0));
+    if std::path::Path::new(PATH_FILE).exists() {
+        match raymarcher::path::CameraPath::load(PATH_FILE) {
+            Ok(path) => ray_marcher.path = path,
+            Err(e) => log::error!("Failed to load {PATH_FILE}: {e}"),
+        }
+    }
+    if std::path::Path::new(ENVIRONMENT_FILE).exists() {
+        if let Err(e) = ray_marcher.load_environment(ENVIRONMENT_FILE) {
+            log::error!("Failed to load {ENVIRONMENT_FILE}: {e}");
+        }
+    }
+    if std::path::Path::new(HEIGHTMAP_FILE).exists() {
+        if let Err(e) = ray_marcher.load_heightmap(HEIGHTMAP_FILE) {
+            log::error!("Failed to load {HEIGHTMAP_FILE}: {e}");
+        }
+    }
+    if std::path::Path::new(MESH_FILE).exists() {
+        if let Err(e) = ray_marcher.load_mesh(MESH_FILE) {
+            log::error!("Failed to load {MESH_FILE}: {e}");
+        }
+    }
+    let mut bookmarks = Bookmarks::default();
+    if std::path::Path::new(BOOKMARKS_FILE).exists() {
+        match Bookmarks::load(BOOKMARKS_FILE) {
+            Ok(loaded) => bookmarks = loaded,
+            Err(e) => log::error!("Failed to load {BOOKMARKS_FILE}: {e}"),
+        }
+    }
+    let mut input_map = InputMap::default();
+    if std::path::Path::new(BINDINGS_FILE).exists() {
+        match InputMap::load(BINDINGS_FILE) {
+            Ok(loaded) => input_map = loaded,
+            Err(e) => log::error!("Failed to load {BINDINGS_FILE}: {e}"),
+        }
+    }
+    #[cfg(feature = "gamepad")]
+    let mut gilrs = gilrs::Gilrs::new()
+        .map_err(|e| log::error!("Failed to start gamepad input: {e}"))
+        .ok();
+
+    let mut app = App {
+        ray_marcher,
+        bookmarks,
+        input: InputState::default(),
+        modifiers: ModifiersState::empty(),
+        show_stats: false,
+    };
 
-    let mut dm = (0.0, 0.0);
+    let mut stats = (0, 0.0);
 
     event_loop.run(move |event, _, control_flow| {
-        let window = &ray_marcher.wgpu_ctx.window;
+        let window = &app.ray_marcher.wgpu_ctx.window;
         match event {
             Event::WindowEvent {
                 ref event,
                 window_id,
             } if window_id == window.id() => match event {
-                WindowEvent::CloseRequested
-                | WindowEvent::KeyboardInput {
-                    input:
-                        KeyboardInput {
-                            state: ElementState::Pressed,
-                            virtual_keycode: Some(VirtualKeyCode::Escape),
-                            ..
-                        },
-                    ..
-                } => *control_flow = ControlFlow::Exit,
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
                 WindowEvent::KeyboardInput { input, .. } => {
-                    let state = ElementState::Pressed == input.state;
-                    match input.scancode {
-                        W => {
-                            ray_marcher.controller.forwards = state;
-                        }
-                        A => {
-                            ray_marcher.controller.left = state;
-                        }
-                        S => {
-                            ray_marcher.controller.backwards = state;
-                        }
-                        D => {
-                            ray_marcher.controller.right = state;
-                        }
-                        Q => {
-                            ray_marcher.controller.up = state;
-                        }
-                        E => {
-                            ray_marcher.controller.down = state;
+                    let pressed = ElementState::Pressed == input.state;
+                    // a key can be bound both by what it types and where it is, run its actions once
+                    let mut actions = Vec::new();
+                    for action in
+                        Binding::keyboard(input).flat_map(|binding| input_map.actions(binding))
+                    {
+                        if !actions.contains(&action) {
+                            actions.push(action);
                         }
-                        SPACE => {
-                            if !state {
-                                ray_marcher.switch_scene();
-                            }
+                    }
+                    for action in actions {
+                        if app.action(action, pressed) {
+                            *control_flow = ControlFlow::Exit;
                         }
-                        _ => {}
                     }
                 }
                 WindowEvent::MouseInput { state, button, .. } => {
-                    if let MouseButton::Left = button {
-                        let state = ElementState::Pressed == *state;
-                        ray_marcher.controller.looking = state;
+                    let pressed = ElementState::Pressed == *state;
+                    for action in input_map.actions(Binding::Mouse(*button)) {
+                        if app.action(action, pressed) {
+                            *control_flow = ControlFlow::Exit;
+                        }
                     }
                 }
+                WindowEvent::MouseWheel { delta, .. } => {
+                    let amount = match delta {
+                        MouseScrollDelta::LineDelta(_, y) => *y * 0.1,
+                        MouseScrollDelta::PixelDelta(p) => p.y as f32 * 0.002,
+                    };
+                    app.input.add_zoom(amount);
+                }
+                WindowEvent::ModifiersChanged(state) => {
+                    app.modifiers = *state;
+                }
                 WindowEvent::Resized(physical_size) => {
-                    ray_marcher.resize((*physical_size).into());
+                    app.ray_marcher.resize((*physical_size).into());
                 }
                 WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
                     // new_inner_size is &&mut so we have to dereference it twice
-                    ray_marcher.resize((**new_inner_size).into())
+                    app.ray_marcher.resize((**new_inner_size).into())
                 }
                 WindowEvent::Focused(false) => {
-                    ray_marcher.controller = raymarcher::Controller::default();
+                    app.input.release();
+                }
+                WindowEvent::DroppedFile(path) => {
+                    // .hdr images are environments, .obj and .stl meshes, anything else a heightmap
+                    let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+                    let result = match extension.as_deref() {
+                        Some("hdr") => app.ray_marcher.load_environment(path),
+                        Some("obj" | "stl") => app.ray_marcher.load_mesh(path),
+                        _ => app.ray_marcher.load_heightmap(path),
+                    };
+                    if let Err(e) = result {
+                        log::error!("Failed to load {}: {e}", path.display());
+                    }
                 }
                 _ => {}
             },
@@ -101,21 +268,13 @@ fn main() {
                 event: DeviceEvent::MouseMotion { delta },
                 ..
             } => {
-                dm.0 += delta.0;
-                dm.1 += delta.1;
+                app.input.add_mouse(delta.0 as f32, delta.1 as f32);
             }
             Event::RedrawRequested(window_id) if window_id == window.id() => {
-                if ray_marcher.controller.looking {
-                    let sensativity = 0.005;
-                    let v = ray_marcher.camera.rot.transform_vector(
-                        &(raymarcher::na::Vector3::new(dm.1 as f32, dm.0 as f32, 0.0)
-                            * sensativity),
-                    );
-                    ray_marcher.camera.rot = ray_marcher.camera.rot.append_axisangle_linearized(&v);
-                    // log::info!("{}", ray_marcher.camera.rot.angle());
-                }
-                dm.0 = 0.0;
-                dm.1 = 0.0;
+                let ray_marcher = &mut app.ray_marcher;
+                app.input
+                    .update_controller(&input_map, &mut ray_marcher.controller);
+                ray_marcher.controller.panning = app.modifiers.shift();
                 ray_marcher.update(dt);
                 match ray_marcher.render() {
                     Ok(_) => {}
@@ -127,13 +286,64 @@ fn main() {
                     Err(e) => log::error!("{:?}", e),
                 }
 
+                if app.show_stats {
+                    stats.0 += 1;
+                    stats.1 += dt;
+                    if stats.1 >= 0.5 {
+                        let pos = ray_marcher.camera.pos;
+                        ray_marcher.wgpu_ctx.window.set_title(&format!(
+                            "{TITLE} - {:.0} fps - ({:.3}, {:.3}, {:.3})",
+                            stats.0 as f32 / stats.1,
+                            pos.x,
+                            pos.y,
+                            pos.z
+                        ));
+                        stats = (0, 0.0);
+                    }
+                }
+
                 dt = timer.elapsed().as_secs_f32();
                 timer = std::time::Instant::now();
             }
             Event::MainEventsCleared => {
+                #[cfg(feature = "gamepad")]
+                while let Some(gilrs::Event { event, .. }) =
+                    gilrs.as_mut().and_then(|gilrs| gilrs.next_event())
+                {
+                    let (button, pressed) = match event {
+                        gilrs::EventType::ButtonPressed(button, _) => (button, true),
+                        gilrs::EventType::ButtonReleased(button, _) => (button, false),
+                        gilrs::EventType::AxisChanged(axis, value, _) => {
+                            if let Some(axis) = input::GamepadAxis::from_gilrs(axis) {
+                                app.input.set_gamepad_axis(axis, value);
+                            }
+                            continue;
+                        }
+                        gilrs::EventType::ButtonChanged(button, value, _) => {
+                            if let Some(axis) = input::GamepadAxis::from_gilrs_button(button) {
+                                app.input.set_gamepad_axis(axis, value);
+                            }
+                            continue;
+                        }
+                        gilrs::EventType::Disconnected => {
+                            app.input.release();
+                            continue;
+                        }
+                        _ => continue,
+                    };
+                    let Some(button) = input::GamepadButton::from_gilrs(button) else {
+                        continue;
+                    };
+                    for action in input_map.actions(Binding::Gamepad(button)) {
+                        if app.action(action, pressed) {
+                            *control_flow = ControlFlow::Exit;
+                        }
+                    }
+                }
+
                 // RedrawRequested will only trigger once, unless we manually
                 // request it.
-                window.request_redraw();
+                app.ray_marcher.wgpu_ctx.window.request_redraw();
             }
             _ => {}
         };
@@ -0,0 +1,277 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    default_settings,
+    distance_cache::{self, DistanceCache},
+    na,
+    offline::OfflineRenderer,
+    path::{CameraPath, Keyframe},
+    Camera, SettingsUniform,
+};
+
+/// Milliseconds spent on a frame. `total` is measured on the cpu and includes waiting for the gpu,
+/// the passes are measured on the gpu.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FrameTime {
+    pub total: f32,
+    pub raymarcher: Option<f32>,
+    pub fullscreen: Option<f32>,
+}
+
+/// A camera path through one scene.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Benchmark {
+    pub name: String,
+    pub settings: SettingsUniform,
+    pub path: CameraPath,
+    /// Bakes a distance cache around the origin first, the scene can't change along the path.
+    pub distance_cache: bool,
+}
+
+fn keyframe(time: f32, pos: na::Point3<f32>, target: na::Point3<f32>, scene_time: f32) -> Keyframe {
+    let camera = Camera {
+        pos,
+        rot: na::UnitQuaternion::face_towards(&(target - pos), &na::Vector3::y()),
+        ..Default::default()
+    };
+    Keyframe::from_camera(time, &camera, scene_time)
+}
+
+const ORBIT_KEYFRAMES: usize = 8;
+// circles the origin looking at it
+fn orbit_path(radius: f32, height: f32, duration: f32, animated: bool) -> CameraPath {
+    let mut path = CameraPath::default();
+    for i in 0..=ORBIT_KEYFRAMES {
+        let t = i as f32 / ORBIT_KEYFRAMES as f32;
+        let angle = t * std::f32::consts::TAU;
+        let pos = na::point![radius * angle.sin(), height, -radius * angle.cos()];
+        let time = t * duration;
+        let scene_time = if animated { time } else { 0.0 };
+        path.insert(keyframe(time, pos, na::Point3::origin(), scene_time));
+    }
+    path
+}
+// flies straight towards the origin
+fn dive_path(from: na::Point3<f32>, to: na::Point3<f32>, duration: f32) -> CameraPath {
+    let mut path = CameraPath::default();
+    path.insert(keyframe(0.0, from, na::Point3::origin(), 0.0));
+    path.insert(keyframe(duration, to, na::Point3::origin(), 0.0));
+    path
+}
+
+/// The built in benchmarks, an orbit and a dive through each scene.
+pub fn benchmarks() -> Vec<Benchmark> {
+    let mandelbulb = default_settings();
+    let mut menger_sponge = default_settings();
+    menger_sponge.set_mengersponge_mut();
+    let mut terrain = default_settings();
+    terrain.set_terrain_mut();
+
+    vec![
+        Benchmark {
+            name: "mandelbulb-orbit".to_string(),
+            settings: mandelbulb,
+            path: orbit_path(2.5, 0.5, 10.0, true),
+            distance_cache: false,
+        },
+        Benchmark {
+            name: "mandelbulb-dive".to_string(),
+            settings: mandelbulb,
+            path: dive_path(na::point![0.0, 0.0, -3.0], na::point![0.0, 0.0, -1.3], 10.0),
+            distance_cache: false,
+        },
+        Benchmark {
+            name: "menger-sponge-orbit".to_string(),
+            settings: menger_sponge,
+            path: orbit_path(3.0, 1.0, 10.0, false),
+            distance_cache: false,
+        },
+        Benchmark {
+            name: "menger-sponge-orbit-cached".to_string(),
+            settings: menger_sponge,
+            path: orbit_path(3.0, 1.0, 10.0, false),
+            distance_cache: true,
+        },
+        Benchmark {
+            name: "menger-sponge-dive".to_string(),
+            settings: menger_sponge,
+            // into the hole in the middle of a face
+            path: dive_path(na::point![0.0, 0.0, -3.0], na::point![0.0, 0.0, -0.9], 10.0),
+            distance_cache: false,
+        },
+        Benchmark {
+            name: "terrain-orbit".to_string(),
+            settings: terrain,
+            // just above the peaks, looking down into the valleys
+            path: orbit_path(4.0, 0.5, 10.0, false),
+            distance_cache: false,
+        },
+    ]
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    pub sizes: Vec<(u32, u32)>,
+    /// Frames measured along each path, at least one.
+    pub frames: u32,
+    /// Frames rendered at the start of each path before measuring.
+    pub warmup: u32,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            sizes: vec![(640, 360), (1280, 720), (1920, 1080)],
+            frames: 120,
+            warmup: 10,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    pub mean: f32,
+    pub median: f32,
+    pub p95: f32,
+    pub min: f32,
+    pub max: f32,
+}
+impl Stats {
+    pub fn new(times: impl IntoIterator<Item = f32>) -> Option<Self> {
+        let mut times: Vec<f32> = times.into_iter().collect();
+        if times.is_empty() {
+            return None;
+        }
+        times.sort_by(f32::total_cmp);
+        let percentile = |p: f32| times[((times.len() - 1) as f32 * p).round() as usize];
+        Some(Self {
+            mean: times.iter().sum::<f32>() / times.len() as f32,
+            median: percentile(0.5),
+            p95: percentile(0.95),
+            min: times[0],
+            max: times[times.len() - 1],
+        })
+    }
+}
+
+/// One benchmark at one size.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Run {
+    pub name: String,
+    pub width: u32,
+    pub height: u32,
+    pub total: Stats,
+    pub raymarcher: Option<Stats>,
+    pub fullscreen: Option<Stats>,
+    pub frames: Vec<FrameTime>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Report {
+    pub adapter: String,
+    pub backend: String,
+    pub runs: Vec<Run>,
+}
+impl Report {
+    pub fn get(&self, name: &str, size: (u32, u32)) -> Option<&Run> {
+        self.runs
+            .iter()
+            .find(|run| run.name == name && (run.width, run.height) == size)
+    }
+
+    /// Every frame of every run, one per line.
+    pub fn to_csv(&self) -> String {
+        let ms = |time: Option<f32>| time.map_or(String::new(), |time| time.to_string());
+        let mut csv = "name,width,height,frame,total_ms,raymarcher_ms,fullscreen_ms\n".to_string();
+        for run in &self.runs {
+            for (i, frame) in run.frames.iter().enumerate() {
+                csv += &format!(
+                    "{},{},{},{},{},{},{}\n",
+                    run.name,
+                    run.width,
+                    run.height,
+                    i,
+                    frame.total,
+                    ms(frame.raymarcher),
+                    ms(frame.fullscreen)
+                );
+            }
+        }
+        csv
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&file)?)
+    }
+    /// Saves as csv if the path ends in `.csv` and json otherwise. Only json can be loaded again.
+    pub fn save(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let file = if path.extension().is_some_and(|e| e == "csv") {
+            self.to_csv()
+        } else {
+            serde_json::to_string_pretty(self)?
+        };
+        std::fs::write(path, file)?;
+        Ok(())
+    }
+}
+
+/// Renders every benchmark at every size in `config`.
+pub fn run(renderer: &mut OfflineRenderer, benchmarks: &[Benchmark], config: &Config) -> Report {
+    let mut runs = Vec::new();
+    for &size in &config.sizes {
+        renderer.resize(size);
+        for benchmark in benchmarks {
+            let cache = benchmark.distance_cache.then(|| {
+                DistanceCache::bake(
+                    &benchmark.settings,
+                    renderer.scene_data(),
+                    na::Point3::origin(),
+                    benchmark.settings.max_dist,
+                    distance_cache::DEFAULT_RESOLUTION,
+                )
+            });
+            renderer.set_distance_cache(cache);
+            let start = benchmark.path.keyframes().first().map_or(0.0, |k| k.time);
+            let duration = benchmark.path.duration();
+            let mut render = |time: f32| {
+                let mut camera = Camera::default();
+                *renderer.settings_mut() = benchmark.settings;
+                if let Some(keyframe) = benchmark.path.sample(start + time) {
+                    keyframe.apply(&mut camera);
+                    renderer.settings_mut().time = keyframe.scene_time;
+                }
+                renderer.render_timed(&camera)
+            };
+
+            for _ in 0..config.warmup {
+                render(0.0);
+            }
+            let count = config.frames.max(1);
+            let frames: Vec<_> = (0..count)
+                .map(|i| render(duration * i as f32 / (count - 1).max(1) as f32))
+                .collect();
+            log::info!("{} at {}x{} done", benchmark.name, size.0, size.1);
+
+            runs.push(Run {
+                name: benchmark.name.clone(),
+                width: size.0,
+                height: size.1,
+                total: Stats::new(frames.iter().map(|f| f.total)).unwrap(),
+                raymarcher: Stats::new(frames.iter().filter_map(|f| f.raymarcher)),
+                fullscreen: Stats::new(frames.iter().filter_map(|f| f.fullscreen)),
+                frames,
+            });
+        }
+    }
+
+    let info = renderer.adapter_info();
+    Report {
+        adapter: info.name.clone(),
+        backend: format!("{:?}", info.backend),
+        runs,
+    }
+}
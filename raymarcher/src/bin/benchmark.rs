@@ -0,0 +1,125 @@
+use raymarcher::{
+    benchmark::{self, Config, Report, Stats},
+    offline::OfflineRenderer,
+};
+
+const USAGE: &str = "Usage: benchmark [options] [report.json|report.csv]
+
+Options:
+  --frames <n>          frames measured along each path
+  --warmup <n>          frames rendered before measuring
+  --size <w>x<h>        size to render at, can be given more than once
+  --only <name>         only run benchmarks with names containing this
+  --compare <file>      print how frame times changed from an earlier json report";
+
+struct Args {
+    output: Option<std::path::PathBuf>,
+    config: Config,
+    only: Option<String>,
+    compare: Option<std::path::PathBuf>,
+}
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Args {
+            output: None,
+            config: Config::default(),
+            only: None,
+            compare: None,
+        };
+
+        let mut sizes = Vec::new();
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--frames" => args.config.frames = parse(&value()?)?,
+                "--warmup" => args.config.warmup = parse(&value()?)?,
+                "--size" => {
+                    let value = value()?;
+                    let (w, h) = value
+                        .split_once('x')
+                        .ok_or(format!("invalid size '{value}'"))?;
+                    sizes.push((parse(w)?, parse(h)?));
+                }
+                "--only" => args.only = Some(value()?),
+                "--compare" => args.compare = Some(value()?.into()),
+                _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+                _ => args.output = Some(arg.into()),
+            }
+        }
+        if !sizes.is_empty() {
+            args.config.sizes = sizes;
+        }
+
+        Ok(args)
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}'"))
+}
+
+fn ms(stats: Option<Stats>) -> String {
+    stats.map_or("-".to_string(), |stats| format!("{:.3}", stats.mean))
+}
+
+fn main() {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+    let previous = args.compare.as_ref().map(|path| {
+        Report::load(path).unwrap_or_else(|e| {
+            eprintln!("failed to load {}: {e}", path.display());
+            std::process::exit(1);
+        })
+    });
+
+    let benchmarks: Vec<_> = benchmark::benchmarks()
+        .into_iter()
+        .filter(|b| args.only.as_ref().is_none_or(|only| b.name.contains(only)))
+        .collect();
+
+    let mut renderer = pollster::block_on(OfflineRenderer::new(args.config.sizes[0]));
+    let report = benchmark::run(&mut renderer, &benchmarks, &args.config);
+
+    println!("{} ({})", report.adapter, report.backend);
+    println!(
+        "{:<24}{:>11}{:>11}{:>11}{:>11}{:>15}{:>11}",
+        "benchmark", "size", "mean ms", "p95 ms", "march ms", "fullscreen ms", "change"
+    );
+    for run in &report.runs {
+        let size = (run.width, run.height);
+        let change = previous
+            .as_ref()
+            .and_then(|previous| previous.get(&run.name, size))
+            .map_or(String::new(), |before| {
+                format!(
+                    "{:+.1}%",
+                    (run.total.mean / before.total.mean - 1.0) * 100.0
+                )
+            });
+        println!(
+            "{:<24}{:>11}{:>11.3}{:>11.3}{:>11}{:>15}{:>11}",
+            run.name,
+            format!("{}x{}", size.0, size.1),
+            run.total.mean,
+            run.total.p95,
+            ms(run.raymarcher),
+            ms(run.fullscreen),
+            change
+        );
+    }
+
+    if let Some(output) = &args.output {
+        if let Err(e) = report.save(output) {
+            eprintln!("failed to save {}: {e}", output.display());
+            std::process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use raymarcher::{
+    distance_cache::{self, DistanceCache},
+    offline::OfflineRenderer,
+    recording::Recording,
+    Camera, Projection, StereoMode,
+};
+
+const USAGE: &str = "Usage: render [options] <output.png>
+
+Options:
+  --scene <n>           0 for the mandelbulb, 1 for the menger sponge, 2 for the terrain
+  --size <w>x<h>        image size, cubemap faces use the height
+  --projection <name>   perspective, orthographic, equirectangular, fisheye or cubemap
+  --fov <degrees>       field of view of the fisheye projection
+  --samples <n>         samples per pixel
+  --time <seconds>      scene time
+  --fog <density>       height fog, around 0.1 is light fog
+  --fog-samples <n>     samples along each ray for light shafts through the fog
+  --environment <file>  light the scene with an equirectangular .hdr instead of the sky
+  --heightmap <file>    shape the terrain after a greyscale image instead of noise
+  --mesh <file>         add an .obj or .stl mesh to the scene at the origin
+  --distance-cache      bake the scene into a distance cache first, for stills with many samples
+  --stereo <mode>       off, side-by-side, top-bottom or anaglyph
+  --ipd <distance>      distance between the eyes in stereo
+  --replay <file>       render where a recording ends up, using its scene and camera
+  --timestep <seconds>  replay with a fixed timestep instead of the recorded frame times
+  --every-frame         save every frame of the replay as <output>_<frame>.png";
+
+struct Args {
+    output: std::path::PathBuf,
+    scene: u32,
+    size: (u32, u32),
+    projection: String,
+    fov: f32,
+    samples: u32,
+    time: f32,
+    fog: f32,
+    fog_samples: u32,
+    environment: Option<std::path::PathBuf>,
+    heightmap: Option<std::path::PathBuf>,
+    mesh: Option<std::path::PathBuf>,
+    distance_cache: bool,
+    stereo: String,
+    ipd: Option<f32>,
+    replay: Option<std::path::PathBuf>,
+    timestep: Option<f32>,
+    every_frame: bool,
+}
+impl Args {
+    fn parse() -> Result<Self, String> {
+        let mut args = Args {
+            output: Default::default(),
+            scene: 0,
+            size: (1920, 1080),
+            projection: "perspective".to_string(),
+            fov: 180.0,
+            samples: 1,
+            time: 0.0,
+            fog: 0.0,
+            fog_samples: 0,
+            environment: None,
+            heightmap: None,
+            mesh: None,
+            distance_cache: false,
+            stereo: "off".to_string(),
+            ipd: None,
+            replay: None,
+            timestep: None,
+            every_frame: false,
+        };
+
+        let mut output = None;
+        let mut iter = std::env::args().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = || iter.next().ok_or(format!("missing value for {arg}"));
+            match arg.as_str() {
+                "--scene" => args.scene = parse(&value()?)?,
+                "--size" => {
+                    let value = value()?;
+                    let (w, h) = value
+                        .split_once('x')
+                        .ok_or(format!("invalid size '{value}'"))?;
+                    args.size = (parse(w)?, parse(h)?);
+                }
+                "--projection" => args.projection = value()?,
+                "--fov" => args.fov = parse(&value()?)?,
+                "--samples" => args.samples = parse(&value()?)?,
+                "--time" => args.time = parse(&value()?)?,
+                "--fog" => args.fog = parse(&value()?)?,
+                "--fog-samples" => args.fog_samples = parse(&value()?)?,
+                "--environment" => args.environment = Some(value()?.into()),
+                "--heightmap" => args.heightmap = Some(value()?.into()),
+                "--mesh" => args.mesh = Some(value()?.into()),
+                "--distance-cache" => args.distance_cache = true,
+                "--stereo" => args.stereo = value()?,
+                "--ipd" => args.ipd = Some(parse(&value()?)?),
+                "--replay" => args.replay = Some(value()?.into()),
+                "--timestep" => args.timestep = Some(parse(&value()?)?),
+                "--every-frame" => args.every_frame = true,
+                _ if arg.starts_with("--") => return Err(format!("unknown option {arg}")),
+                _ => output = Some(arg.into()),
+            }
+        }
+        args.output = output.ok_or("missing output path")?;
+
+        Ok(args)
+    }
+}
+
+fn parse<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value '{value}'"))
+}
+
+fn main() {
+    let args = match Args::parse() {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("{e}\n\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+
+    let projection = match args.projection.as_str() {
+        "perspective" | "cubemap" => Projection::Perspective,
+        "orthographic" => Projection::Orthographic { height: 3.0 },
+        "equirectangular" => Projection::Equirectangular,
+        "fisheye" => Projection::Fisheye {
+            angle: args.fov.to_radians(),
+        },
+        projection => {
+            eprintln!("unknown projection {projection}\n\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+    let stereo = match args.stereo.as_str() {
+        "off" => StereoMode::Off,
+        "side-by-side" => StereoMode::SideBySide,
+        "top-bottom" => StereoMode::TopBottom,
+        "anaglyph" => StereoMode::Anaglyph,
+        stereo => {
+            eprintln!("unknown stereo mode {stereo}\n\n{USAGE}");
+            std::process::exit(1);
+        }
+    };
+    let camera = Camera {
+        projection,
+        ..Default::default()
+    };
+
+    let mut renderer = pollster::block_on(OfflineRenderer::new(args.size));
+    renderer.stereo.mode = stereo;
+    if let Some(ipd) = args.ipd {
+        renderer.stereo.ipd = ipd;
+    }
+    if let Some(path) = &args.environment {
+        if let Err(e) = renderer.load_environment(path) {
+            eprintln!("failed to load {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    }
+    if let Some(path) = &args.heightmap {
+        if let Err(e) = renderer.load_heightmap(path) {
+            eprintln!("failed to load {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    }
+    if let Some(path) = &args.mesh {
+        if let Err(e) = renderer.load_mesh(path) {
+            eprintln!("failed to load {}: {e}", path.display());
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = &args.replay {
+        let recording = Recording::load(path).unwrap_or_else(|e| {
+            eprintln!("failed to load {}: {e}", path.display());
+            std::process::exit(1);
+        });
+        let stem = args.output.file_stem().unwrap().to_string_lossy();
+        let mut frame = 0;
+        let mut last = (recording.start.camera, recording.start.settings);
+        let data = renderer.scene_data().clone();
+        recording.simulate(args.timestep, &data, |camera, settings| {
+            if args.every_frame {
+                *renderer.settings_mut() = *settings;
+                let image = renderer.render(camera, args.samples);
+                let path = args.output.with_file_name(format!("{stem}_{frame:05}.png"));
+                image.save(path).unwrap();
+            }
+            frame += 1;
+            last = (*camera, *settings);
+        });
+        if !args.every_frame {
+            *renderer.settings_mut() = last.1;
+            let image = renderer.render(&last.0, args.samples);
+            image.save(&args.output).unwrap();
+        }
+        return;
+    }
+
+    let settings = renderer.settings_mut();
+    match args.scene {
+        1 => settings.set_mengersponge_mut(),
+        2 => settings.set_terrain_mut(),
+        _ => settings.set_mandelbulb_mut(),
+    }
+    settings.time = args.time;
+    settings.fog_density = args.fog;
+    settings.fog_samples = args.fog_samples;
+    if args.distance_cache {
+        let settings = renderer.settings();
+        let cache = DistanceCache::bake(
+            settings,
+            renderer.scene_data(),
+            camera.pos,
+            settings.max_dist,
+            distance_cache::DEFAULT_RESOLUTION,
+        );
+        renderer.set_distance_cache(Some(cache));
+    }
+
+    if args.projection == "cubemap" {
+        renderer.resize((args.size.1, args.size.1));
+        let faces = renderer.render_cubemap(&camera, args.samples);
+
+        let stem = args.output.file_stem().unwrap().to_string_lossy();
+        for (face, name) in faces.iter().zip(["px", "nx", "py", "ny", "pz", "nz"]) {
+            let path = args.output.with_file_name(format!("{stem}_{name}.png"));
+            face.save(&path).unwrap();
+        }
+    } else {
+        let image = renderer.render(&camera, args.samples);
+        image.save(&args.output).unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Camera, SettingsUniform};
+
+/// A viewpoint along with the scene and settings it was found in.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bookmark {
+    pub camera: Camera,
+    pub settings: SettingsUniform,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bookmarks {
+    bookmarks: BTreeMap<String, Bookmark>,
+}
+impl Bookmarks {
+    pub fn insert(&mut self, name: impl Into<String>, bookmark: Bookmark) {
+        self.bookmarks.insert(name.into(), bookmark);
+    }
+    pub fn get(&self, name: &str) -> Option<&Bookmark> {
+        self.bookmarks.get(name)
+    }
+    pub fn remove(&mut self, name: &str) -> Option<Bookmark> {
+        self.bookmarks.remove(name)
+    }
+    /// Names of all bookmarks in alphabetical order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.bookmarks.keys().map(String::as_str)
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::read_to_string(path)?;
+        Ok(ron::from_str(&file)?)
+    }
+    pub fn save(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let file = ron::ser::to_string_pretty(self, Default::default())?;
+        std::fs::write(path, file)?;
+        Ok(())
+    }
+}
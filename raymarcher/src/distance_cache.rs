@@ -0,0 +1,343 @@
+use crate::{
+    domain::{DomainOp, MAX_DOMAIN_OPS},
+    na, sdf, SceneData, SettingsUniform,
+};
+
+/// Grid points along each side of the cache when it isn't given.
+pub const DEFAULT_RESOLUTION: u32 = 128;
+/// Grid points along each side of a brick, same as `DISTANCE_CACHE_BRICK` in raymarcher.wgsl.
+pub const BRICK_SIZE: u32 = 8;
+// bricks further than this many cells from the surface get a single distance instead of a grid
+const NARROW_BAND: f32 = 4.0;
+// the nearest grid point is up to half a cell's diagonal away, same as in raymarcher.wgsl
+const MARGIN: f32 = 1.0;
+
+// everything in the settings the distance estimate depends on
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Shape {
+    scene: u32,
+    time: f32,
+    domain: [DomainOp; MAX_DOMAIN_OPS],
+    terrain_height: f32,
+    terrain_scale: f32,
+    terrain_octaves: u32,
+    mesh_position: [f32; 3],
+    mesh_scale: f32,
+}
+impl Shape {
+    fn new(settings: &SettingsUniform) -> Self {
+        Self {
+            scene: settings.scene,
+            // only the mandelbulb changes over time
+            time: if settings.scene == 0 {
+                settings.time
+            } else {
+                0.0
+            },
+            domain: settings.domain,
+            terrain_height: settings.terrain_height,
+            terrain_scale: settings.terrain_scale,
+            terrain_octaves: settings.terrain_octaves,
+            mesh_position: settings.mesh_position,
+            mesh_scale: settings.mesh_scale,
+        }
+    }
+}
+
+// laid out like the CacheBrick struct in raymarcher.wgsl
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub(crate) struct Brick {
+    // a distance that's safe to step anywhere in the brick, when it's far from the surface
+    pub(crate) distance: f32,
+    // 1 + which grid of distances the brick has when it's near the surface, 0 when it's far
+    pub(crate) dense: u32,
+}
+
+/// The scene's distance estimate baked into a sparse grid, so rays can cross empty space with a
+/// lookup instead of evaluating the fractal.
+///
+/// The cube is split into bricks of `BRICK_SIZE` cells a side. Bricks far from the surface only
+/// keep one distance for all of their cells and the ones near it keep every cell, so only those
+/// cost a full grid to bake and store. Close to the surface the exact estimate is used, so the
+/// grid can be coarse. It's only used while the settings that shape the scene stay the ones it
+/// was baked with, so it's for scenes that hold still.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DistanceCache {
+    // bricks along each side
+    bricks: u32,
+    // corner of the grid, the distances are at the centres of its cells
+    min: na::Point3<f32>,
+    cell: f32,
+    brick_map: Vec<Brick>,
+    // BRICK_SIZE^3 distances for every brick near the surface, one after the other
+    distances: Vec<f32>,
+    shape: Shape,
+}
+impl DistanceCache {
+    /// Samples the scene in a cube `width` units wide around `center`, with `resolution` points
+    /// along each side, rounded up to whole bricks.
+    pub fn bake(
+        settings: &SettingsUniform,
+        data: &SceneData,
+        center: na::Point3<f32>,
+        width: f32,
+        resolution: u32,
+    ) -> Self {
+        let bricks = resolution.div_ceil(BRICK_SIZE).max(1);
+        let n = bricks as usize;
+        let cell = width / (bricks * BRICK_SIZE) as f32;
+        let min = center - na::Vector3::repeat(width / 2.0);
+        let brick_width = cell * BRICK_SIZE as f32;
+        let half_diagonal = brick_width * 3.0_f32.sqrt() / 2.0;
+
+        // one distance from the middle of every brick to find the ones near the surface
+        let mut brick_map = vec![Brick::default(); n * n * n];
+        parallel(&mut brick_map, |i, brick| {
+            let (x, y, z) = (i % n, i / n % n, i / (n * n));
+            let offset = na::vector![x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5];
+            let d = sdf::de(settings, data, min + offset * brick_width);
+            // nothing in the brick can be closer than its middle less the way to its corners
+            let nearest = d.abs() - half_diagonal;
+            brick.distance = d.signum() * nearest.max(0.0);
+            if nearest <= NARROW_BAND * cell {
+                brick.dense = 1;
+            }
+        });
+        let mut dense = Vec::new();
+        for (i, brick) in brick_map.iter_mut().enumerate() {
+            if brick.dense != 0 {
+                dense.push(i);
+                brick.dense = dense.len() as u32;
+            }
+        }
+
+        // and every cell of those
+        let cells = BRICK_SIZE.pow(3) as usize;
+        let mut distances = vec![0.0; dense.len() * cells];
+        let b = BRICK_SIZE as usize;
+        parallel(&mut distances, |i, distance| {
+            let brick = dense[i / cells];
+            let (bx, by, bz) = (brick % n, brick / n % n, brick / (n * n));
+            let c = i % cells;
+            let (x, y, z) = (bx * b + c % b, by * b + c / b % b, bz * b + c / (b * b));
+            let offset = na::vector![x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5];
+            *distance = sdf::de(settings, data, min + offset * cell);
+        });
+
+        Self {
+            bricks,
+            min,
+            cell,
+            brick_map,
+            distances,
+            shape: Shape::new(settings),
+        }
+    }
+
+    /// Grid points along each side.
+    pub fn resolution(&self) -> u32 {
+        self.bricks * BRICK_SIZE
+    }
+    /// Corner of the cube the grid covers and its width.
+    pub fn bounds(&self) -> (na::Point3<f32>, f32) {
+        (self.min, self.cell * self.resolution() as f32)
+    }
+    /// How many bricks are near enough to the surface to keep every grid point.
+    pub fn dense_bricks(&self) -> usize {
+        self.distances.len() / BRICK_SIZE.pow(3) as usize
+    }
+    pub(crate) fn bricks(&self) -> u32 {
+        self.bricks
+    }
+    pub(crate) fn cell(&self) -> f32 {
+        self.cell
+    }
+    pub(crate) fn brick_map(&self) -> &[Brick] {
+        &self.brick_map
+    }
+    pub(crate) fn distances(&self) -> &[f32] {
+        &self.distances
+    }
+    /// Whether the scene still has the shape the cache was baked from.
+    pub fn matches(&self, settings: &SettingsUniform) -> bool {
+        self.shape == Shape::new(settings)
+    }
+
+    /// The distance the shader steps at `p`, the same way `march_de` looks it up, or `None`
+    /// where it works out the exact estimate instead.
+    pub fn distance(&self, p: na::Point3<f32>) -> Option<f32> {
+        let cells = (p - self.min) / self.cell;
+        let resolution = self.resolution() as f32;
+        if cells.iter().any(|&c| !(0.0..resolution).contains(&c)) {
+            return None;
+        }
+        let c = cells.map(|c| c as u32);
+        let (b, n) = (c / BRICK_SIZE, self.bricks);
+        let brick = self.brick_map[((b.z * n + b.y) * n + b.x) as usize];
+        if brick.dense == 0 {
+            return Some(brick.distance);
+        }
+        let l = c.map(|c| c % BRICK_SIZE);
+        let cell = ((l.z * BRICK_SIZE + l.y) * BRICK_SIZE + l.x) as usize;
+        let d = self.distances[(brick.dense - 1) as usize * BRICK_SIZE.pow(3) as usize + cell];
+        let margin = self.cell * MARGIN;
+        (d.abs() > 2.0 * margin).then(|| d - d.signum() * margin)
+    }
+}
+
+// calls `f` with the index of every value, shared out between threads
+fn parallel<T: Send>(values: &mut [T], f: impl Fn(usize, &mut T) + Sync) {
+    let threads = std::thread::available_parallelism().map_or(1, |t| t.get());
+    let chunk = values.len().div_ceil(threads).max(1);
+    let f = &f;
+    std::thread::scope(|scope| {
+        for (c, values) in values.chunks_mut(chunk).enumerate() {
+            scope.spawn(move || {
+                for (i, value) in values.iter_mut().enumerate() {
+                    f(c * chunk + i, value);
+                }
+            });
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bake(settings: &SettingsUniform) -> DistanceCache {
+        DistanceCache::bake(
+            settings,
+            &SceneData::default(),
+            na::point![0.3, 0.2, -0.1],
+            6.0,
+            32,
+        )
+    }
+
+    // spread through and around the cube, the same every run
+    fn points() -> impl Iterator<Item = na::Point3<f32>> {
+        (0..2000).map(|i| {
+            let x = i as f32;
+            let wrap = |t: f32| (t.fract() * 2.0 - 1.0) * 3.5;
+            na::point![
+                wrap(x * 0.618_034),
+                wrap(x * 0.414_214),
+                wrap(x * 0.732_051)
+            ]
+        })
+    }
+
+    #[test]
+    fn bricks_near_the_surface_hold_the_distances() {
+        for scene in [1, 3] {
+            let settings = SettingsUniform {
+                scene,
+                ..crate::default_settings()
+            };
+            let cache = bake(&settings);
+            assert_eq!(cache.resolution(), 32);
+            assert!(cache.dense_bricks() > 0);
+            assert!(cache.dense_bricks() < cache.brick_map().len());
+
+            let (min, _) = cache.bounds();
+            let n = cache.bricks() as usize;
+            let b = BRICK_SIZE as usize;
+            for (i, brick) in cache.brick_map().iter().enumerate() {
+                if brick.dense == 0 {
+                    continue;
+                }
+                // every grid point in the brick is the estimate at the middle of its cell
+                let first = (brick.dense - 1) as usize * b * b * b;
+                let grid = &cache.distances()[first..first + b * b * b];
+                for (c, &d) in grid.iter().enumerate() {
+                    let cell = na::vector![
+                        (i % n * b + c % b) as f32,
+                        (i / n % n * b + c / b % b) as f32,
+                        (i / (n * n) * b + c / (b * b)) as f32
+                    ];
+                    let p = min + cell.add_scalar(0.5) * cache.cell();
+                    assert_eq!(d, sdf::de(&settings, &SceneData::default(), p));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lookups_never_step_past_the_estimate() {
+        for scene in [1, 3] {
+            let settings = SettingsUniform {
+                scene,
+                ..crate::default_settings()
+            };
+            let cache = bake(&settings);
+            let mut hits = 0;
+            for p in points() {
+                let exact = sdf::de(&settings, &SceneData::default(), p);
+                match cache.distance(p) {
+                    Some(d) => {
+                        hits += 1;
+                        assert!(d.signum() == exact.signum(), "{d} at {p} should be {exact}");
+                        assert!(d.abs() <= exact.abs() + 1e-5, "{d} at {p} is past {exact}");
+                    }
+                    None => {
+                        let (min, width) = cache.bounds();
+                        let inside = (p - min).min() >= 0.0 && (p - min).max() < width;
+                        // only near the surface or outside the cube
+                        assert!(
+                            !inside || exact.abs() < 3.0 * cache.cell(),
+                            "{exact} at {p} is far enough to look up"
+                        );
+                    }
+                }
+                if exact.abs() < cache.cell() {
+                    assert_eq!(cache.distance(p), None, "too close at {p}");
+                }
+            }
+            assert!(hits > 1000);
+        }
+    }
+
+    #[test]
+    fn changing_the_shape_invalidates_the_cache() {
+        let settings = crate::default_settings();
+        let cache = bake(&settings);
+        assert!(cache.matches(&settings));
+
+        let changes: [fn(&mut SettingsUniform); 10] = [
+            |s| s.scene = 1,
+            |s| s.time += 1.0,
+            |s| s.set_domain(&[DomainOp::twist(0.5)]),
+            |s| s.terrain_height += 0.1,
+            |s| s.terrain_scale += 0.1,
+            |s| s.terrain_octaves += 1,
+            |s| s.mesh_position[1] += 0.1,
+            |s| s.mesh_scale *= 2.0,
+            |s| s.set_domain(&[DomainOp::repeat(na::Vector3::repeat(4.0))]),
+            |s| s.set_domain(&[DomainOp::polar(3)]),
+        ];
+        for change in changes {
+            let mut changed = settings;
+            change(&mut changed);
+            assert!(!cache.matches(&changed), "{changed:?}");
+        }
+
+        // and nothing else does
+        let mut looks = settings;
+        looks.fog_density = 0.5;
+        looks.sun_dir = [1.0, 0.0, 0.0];
+        looks.max_steps += 10;
+        assert!(cache.matches(&looks));
+        // only the mandelbulb moves with time
+        let sponge = SettingsUniform {
+            scene: 1,
+            ..settings
+        };
+        let cache = bake(&sponge);
+        assert!(cache.matches(&SettingsUniform {
+            time: 5.0,
+            ..sponge
+        }));
+    }
+}
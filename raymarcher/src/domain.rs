@@ -0,0 +1,95 @@
+use serde::{Deserialize, Serialize};
+
+use crate::na;
+
+/// How many operators the settings have room for.
+pub const MAX_DOMAIN_OPS: usize = 4;
+
+// same as in raymarcher.wgsl, 0 leaves space alone
+pub(crate) const DOMAIN_REPEAT: u32 = 1;
+pub(crate) const DOMAIN_MIRROR: u32 = 2;
+pub(crate) const DOMAIN_POLAR: u32 = 3;
+pub(crate) const DOMAIN_TWIST: u32 = 4;
+pub(crate) const DOMAIN_BEND: u32 = 5;
+pub(crate) const DOMAIN_DISPLACE: u32 = 6;
+
+/// Transforms space before the scene's distance estimator sees it, set with
+/// [`SettingsUniform::set_domain`](crate::SettingsUniform::set_domain).
+/// Operators apply in order, so mirroring and then repeating repeats the mirrored shape.
+#[repr(C)]
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    Default,
+    PartialEq,
+    bytemuck::Pod,
+    bytemuck::Zeroable,
+    Serialize,
+    Deserialize,
+)]
+pub struct DomainOp {
+    // what these mean depends on the kind, see the constructors
+    pub(crate) a: [f32; 3],
+    pub(crate) kind: u32,
+    pub(crate) b: [f32; 3],
+    pub(crate) param: f32,
+}
+impl DomainOp {
+    /// Repeats the scene forever every `period` along each axis, 0 leaves an axis alone.
+    pub fn repeat(period: na::Vector3<f32>) -> Self {
+        Self::repeat_limited(period, na::Vector3::repeat(-1.0))
+    }
+    /// Repeats the scene `limit` times to either side of the original along each axis.
+    pub fn repeat_limited(period: na::Vector3<f32>, limit: na::Vector3<f32>) -> Self {
+        Self {
+            a: period.into(),
+            kind: DOMAIN_REPEAT,
+            b: limit.into(),
+            param: 0.0,
+        }
+    }
+    /// Folds space across the plane `offset` along `normal`, so the side `normal` points to
+    /// shows up mirrored on the other.
+    pub fn mirror(normal: na::Vector3<f32>, offset: f32) -> Self {
+        Self {
+            a: normal.normalize().into(),
+            kind: DOMAIN_MIRROR,
+            param: offset,
+            ..Default::default()
+        }
+    }
+    /// Repeats the slice of the scene around the positive x axis `count` times around the y axis.
+    pub fn polar(count: u32) -> Self {
+        Self {
+            kind: DOMAIN_POLAR,
+            param: count as f32,
+            ..Default::default()
+        }
+    }
+    /// Twists the scene around the y axis by `rate` radians per unit up.
+    pub fn twist(rate: f32) -> Self {
+        Self {
+            kind: DOMAIN_TWIST,
+            param: rate,
+            ..Default::default()
+        }
+    }
+    /// Bends the scene around the z axis by `rate` radians per unit along x.
+    pub fn bend(rate: f32) -> Self {
+        Self {
+            kind: DOMAIN_BEND,
+            param: rate,
+            ..Default::default()
+        }
+    }
+    /// Pushes the surface in and out by up to `amplitude` with noise of `frequency`.
+    pub fn displace(amplitude: f32, frequency: f32) -> Self {
+        Self {
+            a: [frequency, 0.0, 0.0],
+            kind: DOMAIN_DISPLACE,
+            param: amplitude,
+            ..Default::default()
+        }
+    }
+}
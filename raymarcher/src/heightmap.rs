@@ -0,0 +1,120 @@
+use crate::na;
+
+/// Bigger heightmaps are scaled down to this many texels along their longer side, so they fit in
+/// a storage buffer.
+pub const MAX_SIZE: u32 = 4096;
+
+/// Heights from 0 to 1 for the terrain scene, sampled the same way on the CPU and in the shader.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Heightmap {
+    width: u32,
+    height: u32,
+    heights: Vec<f32>,
+    // steepest slope between neighbouring texels
+    slope: f32,
+}
+impl Heightmap {
+    /// `heights` go row by row along x, starting from the row at the -z edge.
+    pub fn new(width: u32, height: u32, heights: Vec<f32>) -> Self {
+        assert!(width > 0 && height > 0, "heightmap can't be empty");
+        assert_eq!(
+            heights.len(),
+            width as usize * height as usize,
+            "heightmap is the wrong size"
+        );
+        if width.max(height) > MAX_SIZE {
+            let longest = width.max(height) as u64;
+            let scale = |size: u32| ((size as u64 * MAX_SIZE as u64 / longest) as u32).max(1);
+            let image = image::ImageBuffer::<image::Luma<f32>, _>::from_raw(width, height, heights)
+                .unwrap();
+            let image = image::imageops::resize(
+                &image,
+                scale(width),
+                scale(height),
+                image::imageops::Triangle,
+            );
+            return Self::new(image.width(), image.height(), image.into_raw());
+        }
+        let at = |x: u32, y: u32| heights[(y * width + x) as usize];
+        let mut steepest = 0.0_f32;
+        for y in 0..height {
+            for x in 0..width {
+                if x + 1 < width {
+                    steepest = steepest.max((at(x + 1, y) - at(x, y)).abs());
+                }
+                if y + 1 < height {
+                    steepest = steepest.max((at(x, y + 1) - at(x, y)).abs());
+                }
+            }
+        }
+        Self {
+            width,
+            height,
+            heights,
+            // blending between texels can slope that much along both axes at once
+            slope: steepest * std::f32::consts::SQRT_2,
+        }
+    }
+    /// Grey levels as heights, 16 bit images keep their precision.
+    pub fn from_image(image: &image::DynamicImage) -> Self {
+        let image = image.to_luma32f();
+        Self::new(image.width(), image.height(), image.into_raw())
+    }
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(Self::from_image(&image::open(path)?))
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    fn texel(&self, x: i32, y: i32) -> f32 {
+        let x = x.clamp(0, self.width as i32 - 1) as u32;
+        let y = y.clamp(0, self.height as i32 - 1) as u32;
+        self.heights[(y * self.width + x) as usize]
+    }
+    /// Blends the four texels around `uv`, which goes from 0 to 1 across the image.
+    pub(crate) fn sample(&self, uv: na::Vector2<f32>) -> f32 {
+        let t = uv.component_mul(&na::vector![self.width as f32, self.height as f32])
+            - na::Vector2::repeat(0.5);
+        let i = t.map(f32::floor);
+        let f = t - i;
+        let (x, y) = (i.x as i32, i.y as i32);
+
+        let mix = |a: f32, b: f32, t: f32| a * (1.0 - t) + b * t;
+        let y0 = mix(self.texel(x, y), self.texel(x + 1, y), f.x);
+        let y1 = mix(self.texel(x, y + 1), self.texel(x + 1, y + 1), f.x);
+        mix(y0, y1, f.y)
+    }
+    /// Steepest the heights change per texel.
+    pub(crate) fn slope(&self) -> f32 {
+        self.slope
+    }
+
+    // laid out like the Heightmap struct in raymarcher.wgsl
+    pub(crate) fn to_bytes(&self) -> Vec<u8> {
+        let mut words = vec![self.width, self.height, self.slope.to_bits()];
+        words.extend(self.heights.iter().map(|h| h.to_bits()));
+        bytemuck::cast_slice(&words).to_vec()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn big_heightmaps_are_scaled_down() {
+        let (width, height) = (MAX_SIZE * 2 + 10, 6);
+        let heights = (0..width * height)
+            .map(|i| (i % width) as f32 / width as f32)
+            .collect();
+        let heightmap = Heightmap::new(width, height, heights);
+        assert_eq!(heightmap.size(), (MAX_SIZE, 2));
+        assert!(heightmap.to_bytes().len() <= 128 << 20);
+        // still the same ramp from one side to the other
+        assert!(heightmap.sample(na::vector![0.0, 0.5]) < 0.01);
+        assert!((heightmap.sample(na::vector![0.5, 0.5]) - 0.5).abs() < 0.01);
+        assert!(heightmap.sample(na::vector![1.0, 0.5]) > 0.99);
+    }
+}
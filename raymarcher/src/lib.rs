@@ -1,28 +1,195 @@
+pub mod benchmark;
+pub mod bookmark;
+pub mod distance_cache;
+pub mod domain;
+pub mod heightmap;
+pub mod light;
+pub mod mesh;
+pub mod movement;
+pub mod noise;
+pub mod offline;
+pub mod orbit;
+pub mod path;
 mod pipeline;
+pub mod recording;
+mod sdf;
 pub mod wgpu_context;
 
+use bookmark::Bookmark;
+use distance_cache::DistanceCache;
+use heightmap::Heightmap;
+use light::{Light, LightKind};
+use mesh::MeshSdf;
+use movement::Movement;
 pub use nalgebra as na;
+use orbit::Orbit;
+use path::CameraPath;
+pub use pipeline::SettingsUniform;
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
+use recording::{Frame, Recording, Replay};
+use serde::{Deserialize, Serialize};
 use wgpu_context::WgpuContext;
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Projection {
+    Perspective,
+    /// `height` is the world space height of the view.
+    Orthographic {
+        height: f32,
+    },
+    /// Full 360 by 180 degree panorama. Render at a 2:1 aspect ratio.
+    Equirectangular,
+    /// Stereographic fisheye. `angle` is the field of view across the height in radians.
+    Fisheye {
+        angle: f32,
+    },
+}
+impl Projection {
+    fn uniform(&self) -> (u32, f32) {
+        match *self {
+            Projection::Perspective => (0, 0.0),
+            Projection::Orthographic { height } => (1, height),
+            Projection::Equirectangular => (2, 0.0),
+            Projection::Fisheye { angle } => (3, angle),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum StereoMode {
+    #[default]
+    Off,
+    SideBySide,
+    TopBottom,
+    /// Red for the left eye and cyan for the right.
+    Anaglyph,
+}
+impl StereoMode {
+    pub fn next(self) -> Self {
+        match self {
+            StereoMode::Off => StereoMode::SideBySide,
+            StereoMode::SideBySide => StereoMode::TopBottom,
+            StereoMode::TopBottom => StereoMode::Anaglyph,
+            StereoMode::Anaglyph => StereoMode::Off,
+        }
+    }
+    // same numbers as in fs_main in raymarcher.wgsl
+    fn uniform(&self) -> u32 {
+        match self {
+            StereoMode::Off => 0,
+            StereoMode::SideBySide => 1,
+            StereoMode::TopBottom => 2,
+            StereoMode::Anaglyph => 3,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stereo {
+    pub mode: StereoMode,
+    /// Distance between the eyes along the camera's right vector.
+    pub ipd: f32,
+}
+impl Default for Stereo {
+    fn default() -> Self {
+        Self {
+            mode: StereoMode::Off,
+            ipd: 0.1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Camera {
     pub pos: na::Point3<f32>,
     pub fov: f32,
     pub rot: na::UnitQuaternion<f32>,
+    /// Diameter of the lens. Zero gives a pinhole camera with everything in focus.
+    pub aperture: f32,
+    /// Distance along the view direction to the plane that is in focus.
+    pub focus_dist: f32,
+    pub projection: Projection,
+}
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            pos: na::point![0.0, 0.0, -3.0],
+            fov: std::f32::consts::FRAC_PI_3,
+            rot: na::UnitQuaternion::default(),
+            aperture: 0.0,
+            focus_dist: 3.0,
+            projection: Projection::Perspective,
+        }
+    }
 }
 impl Camera {
-    fn uniform(&self, aspect: f32) -> pipeline::CameraUniform {
+    fn uniform(&self, aspect: f32, stereo: &Stereo) -> pipeline::CameraUniform {
+        // each eye only gets half of the screen
+        let aspect = match stereo.mode {
+            StereoMode::SideBySide => aspect / 2.0,
+            StereoMode::TopBottom => aspect * 2.0,
+            StereoMode::Off | StereoMode::Anaglyph => aspect,
+        };
         let fov = self.fov / 2.0;
         let mat = na::Matrix4::new_translation(&self.pos.coords)
             * self.rotation().to_homogeneous()
             * na::Matrix4::new_nonuniform_scaling(&na::vector![aspect * fov, fov, 1.0]);
-        pipeline::CameraUniform { matrix: mat.into() }
+        let (projection, projection_param) = self.projection.uniform();
+        pipeline::CameraUniform {
+            matrix: mat.into(),
+            aperture: self.aperture,
+            focus_dist: self.focus_dist,
+            sample: 0,
+            projection,
+            projection_param,
+            stereo: stereo.mode.uniform(),
+            ipd: stereo.ipd,
+            _padding: 0,
+        }
     }
     pub fn rotation(&self) -> na::Rotation3<f32> {
         self.rot.to_rotation_matrix()
     }
+    pub fn forward(&self) -> na::Vector3<f32> {
+        self.rot * na::Vector3::z()
+    }
+    /// Turns the camera by `angle` radians, x to the right and y down like the mouse.
+    pub fn look(&mut self, angle: na::Vector2<f32>) {
+        let v = self
+            .rot
+            .transform_vector(&na::Vector3::new(angle.y, angle.x, 0.0));
+        self.rot = self.rot.append_axisangle_linearized(&v);
+    }
 }
 
+fn default_settings() -> SettingsUniform {
+    SettingsUniform {
+        sun_dir: na::Vector3::new(3.0, 3.0, -3.0).normalize().into(),
+        ..Default::default()
+    }
+    .set_mandelbulb()
+}
+
+// image::open turns hdr images into 8 bit ones
+fn load_hdr(
+    path: impl AsRef<std::path::Path>,
+) -> Result<image::Rgb32FImage, Box<dyn std::error::Error>> {
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    let decoder = image::codecs::hdr::HdrDecoder::new(file)?;
+    let metadata = decoder.metadata();
+    let pixels = decoder.read_image_hdr()?;
+    let image = image::Rgb32FImage::from_raw(
+        metadata.width,
+        metadata.height,
+        pixels.iter().flat_map(|p| p.0).collect(),
+    );
+    Ok(image.ok_or("hdr image is the wrong size")?)
+}
+
+// Samples are accumulated in this format so the running average doesn't lose precision.
+const ACCUMULATION_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
 fn create_texture(
     device: &wgpu::Device,
     size: (u32, u32),
@@ -38,7 +205,7 @@ fn create_texture(
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: format,
+        format,
         usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
         view_formats: &[],
     })
@@ -48,7 +215,6 @@ fn scaled_texture_bindgroup_and_view(
     device: &wgpu::Device,
     texture_bindgroup_layout: &wgpu::BindGroupLayout,
     sampler: &wgpu::Sampler,
-    format: wgpu::TextureFormat,
     size: (u32, u32),
     scale: f32,
 ) -> (wgpu::BindGroup, wgpu::TextureView) {
@@ -56,23 +222,79 @@ fn scaled_texture_bindgroup_and_view(
         ((size.0 as f32 * scale) as u32).max(1),
         ((size.1 as f32 * scale) as u32).max(1),
     );
-    let texture = create_texture(&device, size, format);
+    let texture = create_texture(device, size, ACCUMULATION_FORMAT);
     let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
     let texture_bindgroup =
-        pipeline::texture_bindgroup(&device, &texture_bindgroup_layout, &texture_view, &sampler);
+        pipeline::texture_bindgroup(device, texture_bindgroup_layout, &texture_view, sampler);
 
     (texture_bindgroup, texture_view)
 }
 
-#[derive(Clone, Copy, Debug, Default)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Controller {
-    pub right: bool,
-    pub left: bool,
-    pub down: bool,
-    pub up: bool,
-    pub forwards: bool,
-    pub backwards: bool,
+    /// Movement relative to the camera with x right, y up and z forwards, each from -1 to 1.
+    pub translation: na::Vector3<f32>,
+    /// How fast to turn, x to the right and y down like the mouse, each from -1 to 1.
+    pub rotation: na::Vector2<f32>,
+    /// -1 moves slowest, 0 at normal speed and 1 fastest.
+    pub speed: f32,
     pub looking: bool,
+    /// Mouse movement in pixels since the last frame, only used while looking.
+    pub mouse: na::Vector2<f32>,
+    /// Pans the orbit with the mouse instead of rotating it.
+    pub panning: bool,
+    /// How much to zoom the orbit by since the last frame.
+    pub zoom: f32,
+}
+impl Controller {
+    /// Direction to move in relative to the camera, no longer than 1 so diagonals aren't faster.
+    pub fn direction(&self) -> na::Vector3<f32> {
+        self.translation.cap_magnitude(1.0)
+    }
+}
+
+/// What the scenes are built from besides the settings, kept on the CPU too for collisions and
+/// focusing.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SceneData {
+    pub heightmap: Option<Heightmap>,
+    pub mesh: Option<MeshSdf>,
+}
+
+// Everything a frame of flying or orbiting changes, borrowed from the app or a replay.
+struct Flight<'a> {
+    camera: &'a mut Camera,
+    orbit: &'a mut Option<Orbit>,
+    movement: &'a mut Movement,
+    settings: &'a mut SettingsUniform,
+    paused: bool,
+}
+impl Flight<'_> {
+    // One frame of flying or orbiting, replays step through here too to end up in the same place.
+    fn step(&mut self, controller: &Controller, data: &SceneData, dt: f32) {
+        let movement = &mut *self.movement;
+        let mut look = controller.rotation * movement.look_speed * dt;
+        if controller.looking && !controller.panning {
+            look += controller.mouse * movement.mouse_sensitivity;
+        }
+        if let Some(orbit) = self.orbit {
+            if controller.looking && controller.panning {
+                let pan = controller.mouse * movement.pan_sensitivity;
+                orbit.pan(-pan.x, pan.y);
+            }
+            orbit.rotate(look.x, look.y);
+            orbit.zoom(controller.zoom);
+            orbit.follow(self.camera, dt);
+        } else {
+            self.camera.look(look);
+            movement.update(self.camera, controller, self.settings, data, dt);
+        }
+
+        if !self.paused {
+            self.settings.time += dt;
+        }
+    }
 }
 struct RayMarcherRenderer {
     pipeline: wgpu::RenderPipeline,
@@ -81,14 +303,38 @@ struct RayMarcherRenderer {
 
     camera: pipeline::CameraUniform,
     settings: pipeline::SettingsUniform,
+    settings_bindgroup_layout: wgpu::BindGroupLayout,
     settings_bindgroup: pipeline::BindGroup<pipeline::SettingsUniform>,
 
+    lights: Vec<Light>,
+    lights_buffer: wgpu::Buffer,
+    lights_capacity: usize,
+
+    environment_bindgroup_layout: wgpu::BindGroupLayout,
+    environment: pipeline::Environment,
+    has_environment: bool,
+
+    scene_bindgroup_layout: wgpu::BindGroupLayout,
+    scene_bindgroup: wgpu::BindGroup,
+    scene_data: SceneData,
+    distance_cache: Option<DistanceCache>,
+    // a distance cache baking on another thread
+    baking: Option<std::thread::JoinHandle<DistanceCache>>,
+
+    // what was last drawn into the accumulation texture and how many samples it holds
+    previous: Option<(
+        pipeline::CameraUniform,
+        pipeline::SettingsUniform,
+        Vec<pipeline::LightUniform>,
+    )>,
+    sample: u32,
+
     mesh: pipeline::Mesh<pipeline::Vertex>,
 }
 impl RayMarcherRenderer {
     fn new(
         device: &wgpu::Device,
-        format: wgpu::TextureFormat,
+        queue: &wgpu::Queue,
         settings: pipeline::SettingsUniform,
         camera: pipeline::CameraUniform,
     ) -> Self {
@@ -96,39 +342,224 @@ impl RayMarcherRenderer {
         let settings_bindgroup_layout = pipeline::settings_bindgroup_layout(device);
         let camera_bindgroup = pipeline::camera_bindgroup(device, &camera_bindgroup_layout, camera);
 
-        let settings_bindgroup =
-            pipeline::settings_bindgroup(&device, &settings_bindgroup_layout, settings);
+        let lights = vec![Light::sun()];
+        let lights_buffer = pipeline::lights_buffer(device, lights.len());
+        let settings_bindgroup = pipeline::settings_bindgroup(
+            device,
+            &settings_bindgroup_layout,
+            settings,
+            &lights_buffer,
+        );
+        let environment_bindgroup_layout = pipeline::environment_bindgroup_layout(device);
+        let environment = pipeline::Environment::empty(device, &environment_bindgroup_layout);
+        let scene_bindgroup_layout = pipeline::scene_bindgroup_layout(device);
+        let scene_data = SceneData::default();
+        let scene_bindgroup =
+            pipeline::scene_bindgroup(device, queue, &scene_bindgroup_layout, &scene_data, None);
         let pipeline = pipeline::raymarcher_pipeline(
-            &device,
-            format,
+            device,
+            ACCUMULATION_FORMAT,
             &camera_bindgroup_layout,
             &settings_bindgroup_layout,
+            &environment_bindgroup_layout,
+            &scene_bindgroup_layout,
         );
-        let mesh = pipeline::new_fullscreen_quad(&device);
+        let mesh = pipeline::new_fullscreen_quad(device);
 
         Self {
             pipeline,
             camera,
             camera_bindgroup,
             settings,
+            settings_bindgroup_layout,
             settings_bindgroup,
+            lights_capacity: lights.len(),
+            lights,
+            lights_buffer,
+            environment_bindgroup_layout,
+            environment,
+            has_environment: false,
+            scene_bindgroup_layout,
+            scene_bindgroup,
+            scene_data,
+            distance_cache: None,
+            baking: None,
+            previous: None,
+            sample: 0,
             mesh,
         }
     }
-    fn update(&mut self, queue: &wgpu::Queue) {
-        self.camera_bindgroup.update(queue, self.camera);
-        self.settings_bindgroup.update(queue, self.settings);
+    fn update(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        let lights: Vec<_> = self
+            .lights
+            .iter()
+            .filter(|light| !(self.has_environment && light.kind == LightKind::Sun))
+            .map(|light| light.uniform(self.settings.time, self.settings.sun_dir.into()))
+            .collect();
+        let settings = pipeline::SettingsUniform {
+            light_count: lights.len() as u32,
+            environment: self.has_environment as u32,
+            distance_cache: self
+                .distance_cache
+                .as_ref()
+                .is_some_and(|cache| cache.matches(&self.settings))
+                as u32,
+            ..self.settings
+        };
+
+        if lights.len() > self.lights_capacity {
+            self.lights_capacity = lights.len().next_power_of_two();
+            self.lights_buffer = pipeline::lights_buffer(device, self.lights_capacity);
+            self.settings_bindgroup = pipeline::settings_bindgroup(
+                device,
+                &self.settings_bindgroup_layout,
+                settings,
+                &self.lights_buffer,
+            );
+        }
+
+        // keep adding samples for as long as nothing on screen changes
+        let current = (self.camera, settings, lights);
+        self.sample = if self.previous.as_ref() == Some(&current) {
+            self.sample + 1
+        } else {
+            0
+        };
+
+        let camera = pipeline::CameraUniform {
+            sample: self.sample,
+            ..self.camera
+        };
+        self.camera_bindgroup.update(queue, camera);
+        self.settings_bindgroup.update(queue, settings);
+        queue.write_buffer(&self.lights_buffer, 0, bytemuck::cast_slice(&current.2));
+        self.previous = Some(current);
+    }
+    fn reset_accumulation(&mut self) {
+        self.previous = None;
+    }
+    fn set_environment(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        image: Option<&image::Rgb32FImage>,
+    ) {
+        self.environment = match image {
+            Some(image) => {
+                pipeline::Environment::new(device, queue, &self.environment_bindgroup_layout, image)
+            }
+            None => pipeline::Environment::empty(device, &self.environment_bindgroup_layout),
+        };
+        self.has_environment = image.is_some();
+        self.reset_accumulation();
+    }
+    fn update_scene_bindgroup(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        self.scene_bindgroup = pipeline::scene_bindgroup(
+            device,
+            queue,
+            &self.scene_bindgroup_layout,
+            &self.scene_data,
+            self.distance_cache.as_ref(),
+        );
+        self.reset_accumulation();
+    }
+    // the distance cache was baked from the old heightmap or mesh, so it goes too, along with
+    // any bake that's still going
+    fn set_heightmap(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        heightmap: Option<Heightmap>,
+    ) {
+        self.scene_data.heightmap = heightmap;
+        self.distance_cache = None;
+        self.baking = None;
+        self.update_scene_bindgroup(device, queue);
+    }
+    fn set_mesh(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, mesh: Option<MeshSdf>) {
+        self.scene_data.mesh = mesh;
+        self.distance_cache = None;
+        self.baking = None;
+        self.update_scene_bindgroup(device, queue);
+    }
+    fn set_distance_cache(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        cache: Option<DistanceCache>,
+    ) {
+        self.distance_cache = cache;
+        self.baking = None;
+        self.update_scene_bindgroup(device, queue);
+    }
+    fn bake_distance_cache(&mut self, center: na::Point3<f32>) {
+        let settings = self.settings;
+        let data = self.scene_data.clone();
+        self.baking = Some(std::thread::spawn(move || {
+            DistanceCache::bake(
+                &settings,
+                &data,
+                center,
+                settings.max_dist,
+                distance_cache::DEFAULT_RESOLUTION,
+            )
+        }));
+    }
+    // uses the baked cache once the thread is done with it
+    fn finish_baking(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        if !self
+            .baking
+            .as_ref()
+            .is_some_and(|baking| baking.is_finished())
+        {
+            return;
+        }
+        let baking = self.baking.take().unwrap();
+        match baking.join() {
+            Ok(cache) => {
+                log::info!(
+                    "Baked the distance cache with {} bricks near the surface",
+                    cache.dense_bricks()
+                );
+                self.set_distance_cache(device, queue, Some(cache));
+            }
+            Err(_) => log::error!("Failed to bake the distance cache"),
+        }
     }
-    fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
+    fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Raymarcher Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    // new samples are blended with the previous ones
+                    load: wgpu::LoadOp::Load,
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes,
+            occlusion_query_set: None,
+        });
+
         render_pass.set_pipeline(&self.pipeline);
         render_pass.set_bind_group(0, &self.camera_bindgroup.bindgroup, &[]);
         render_pass.set_bind_group(1, &self.settings_bindgroup.bindgroup, &[]);
-        self.mesh.draw(render_pass);
+        render_pass.set_bind_group(2, &self.environment.bindgroup, &[]);
+        render_pass.set_bind_group(3, &self.scene_bindgroup, &[]);
+        self.mesh.draw(&mut render_pass);
     }
 }
 
 struct FullscreenRenderer {
     pipeline: wgpu::RenderPipeline,
+    format: wgpu::TextureFormat,
 
     texture_bindgroup_layout: wgpu::BindGroupLayout,
     texture_bindgroup: wgpu::BindGroup,
@@ -166,15 +597,15 @@ impl FullscreenRenderer {
             device,
             &texture_bindgroup_layout,
             &sampler,
-            format,
             size,
             scale,
         );
 
-        let mesh = pipeline::new_fullscreen_quad(&device);
+        let mesh = pipeline::new_fullscreen_quad(device);
 
         Self {
             pipeline,
+            format,
             texture_bindgroup_layout,
             texture_bindgroup,
             texture_view,
@@ -182,28 +613,64 @@ impl FullscreenRenderer {
             mesh,
         }
     }
-    fn resize(
-        &mut self,
-        device: &wgpu::Device,
-        format: wgpu::TextureFormat,
-        new_size: (u32, u32),
-        scale: f32,
-    ) {
+    fn resize(&mut self, device: &wgpu::Device, new_size: (u32, u32), scale: f32) {
         let (texture_bindgroup, texture_view) = scaled_texture_bindgroup_and_view(
             device,
             &self.texture_bindgroup_layout,
             &self.sampler,
-            format,
             new_size,
             scale,
         );
         self.texture_bindgroup = texture_bindgroup;
         self.texture_view = texture_view;
     }
-    fn render<'a>(&'a self, render_pass: &mut wgpu::RenderPass<'a>) {
-        render_pass.set_pipeline(&self.pipeline);
+    fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
+    ) {
+        self.render_with(&self.pipeline, encoder, view, timestamp_writes);
+    }
+    /// A pipeline for rendering into textures of another format than the one it was made for.
+    fn pipeline_for(
+        &self,
+        device: &wgpu::Device,
+        format: wgpu::TextureFormat,
+    ) -> Option<wgpu::RenderPipeline> {
+        (format != self.format)
+            .then(|| pipeline::fullscreen_pipeline(device, format, &self.texture_bindgroup_layout))
+    }
+    fn render_with(
+        &self,
+        pipeline: &wgpu::RenderPipeline,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        timestamp_writes: Option<wgpu::RenderPassTimestampWrites>,
+    ) {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Fullscreen Render Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color {
+                        r: 0.1,
+                        g: 0.1,
+                        b: 0.1,
+                        a: 1.0,
+                    }),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes,
+            occlusion_query_set: None,
+        });
+
+        render_pass.set_pipeline(pipeline);
         render_pass.set_bind_group(0, &self.texture_bindgroup, &[]);
-        self.mesh.draw(render_pass);
+        self.mesh.draw(&mut render_pass);
     }
 }
 
@@ -214,8 +681,20 @@ where
     pub wgpu_ctx: WgpuContext<W>,
     pub camera: Camera,
     pub controller: Controller,
+    pub movement: Movement,
+    /// Orbits around a target instead of flying freely when set.
+    pub orbit: Option<Orbit>,
 
     pub scale: f32,
+    pub stereo: Stereo,
+    /// Stops scene time so samples can accumulate on animated scenes.
+    pub paused: bool,
+
+    pub path: CameraPath,
+    // time along the path while it's playing
+    playback: Option<f32>,
+    recording: Option<Recording>,
+    replay: Option<Replay>,
 
     raymarcher_renderer: RayMarcherRenderer,
     fullscreen_renderer: FullscreenRenderer,
@@ -224,27 +703,21 @@ impl<W: HasRawWindowHandle + HasRawDisplayHandle> RayMarcher<W> {
     pub async fn new(window: W, size: (u32, u32), scale: f32) -> Self {
         let wgpu_ctx = WgpuContext::new(window, size).await;
 
-        let camera = Camera {
-            pos: na::point![0.0, 0.0, -3.0],
-            fov: std::f32::consts::FRAC_PI_3,
-            rot: na::UnitQuaternion::default(),
-        };
+        let camera = Camera::default();
 
         let controller = Controller::default();
 
         let aspect = size.0 as f32 / size.1 as f32;
 
-        let settings = pipeline::SettingsUniform {
-            sun_dir: na::Vector3::new(3.0, 3.0, -3.0).normalize().into(),
-            ..Default::default()
-        }
-        .set_mandelbulb();
+        let settings = default_settings();
+
+        let stereo = Stereo::default();
 
         let raymarcher_renderer = RayMarcherRenderer::new(
             &wgpu_ctx.device,
-            wgpu_ctx.config.format,
+            &wgpu_ctx.queue,
             settings,
-            camera.uniform(aspect),
+            camera.uniform(aspect, &stereo),
         );
         let fullscreen_renderer =
             FullscreenRenderer::new(&wgpu_ctx.device, wgpu_ctx.config.format, size, scale);
@@ -253,7 +726,15 @@ impl<W: HasRawWindowHandle + HasRawDisplayHandle> RayMarcher<W> {
             wgpu_ctx,
             camera,
             controller,
+            movement: Movement::default(),
+            orbit: None,
             scale,
+            stereo,
+            paused: false,
+            path: CameraPath::default(),
+            playback: None,
+            recording: None,
+            replay: None,
             raymarcher_renderer,
             fullscreen_renderer,
         }
@@ -264,6 +745,9 @@ impl<W: HasRawWindowHandle + HasRawDisplayHandle> RayMarcher<W> {
                 self.raymarcher_renderer.settings.set_mengersponge_mut();
             }
             1 => {
+                self.raymarcher_renderer.settings.set_terrain_mut();
+            }
+            2 => {
                 self.raymarcher_renderer.settings.set_mandelbulb_mut();
             }
             _ => {
@@ -272,46 +756,228 @@ impl<W: HasRawWindowHandle + HasRawDisplayHandle> RayMarcher<W> {
         }
     }
 
-    pub fn update(&mut self, dt: f32) {
-        let speed = 1.0;
-        let mut dir = na::Vector3::<f32>::zeros();
-        if self.controller.up {
-            dir += na::Vector3::y();
+    pub fn update(&mut self, mut dt: f32) {
+        self.raymarcher_renderer
+            .finish_baking(&self.wgpu_ctx.device, &self.wgpu_ctx.queue);
+
+        if let Some(replay) = &mut self.replay {
+            match replay.next() {
+                Some(frame) => {
+                    self.controller = frame.controller;
+                    dt = frame.dt;
+                }
+                None => {
+                    self.replay = None;
+                    self.controller = Controller::default();
+                }
+            }
         }
-        if self.controller.down {
-            dir -= na::Vector3::y();
+        if let Some(recording) = &mut self.recording {
+            recording.frames.push(Frame {
+                dt,
+                controller: self.controller,
+            });
         }
-        if self.controller.right {
-            dir += na::Vector3::x();
+
+        if let Some(time) = &mut self.playback {
+            *time += dt;
+            if let Some(keyframe) = self.path.sample(*time) {
+                keyframe.apply(&mut self.camera);
+                self.raymarcher_renderer.settings.time = keyframe.scene_time;
+            }
+            if *time >= self.path.keyframes().last().map_or(0.0, |k| k.time) {
+                self.playback = None;
+            }
+            return;
         }
-        if self.controller.left {
-            dir -= na::Vector3::x();
+
+        Flight {
+            camera: &mut self.camera,
+            orbit: &mut self.orbit,
+            movement: &mut self.movement,
+            settings: &mut self.raymarcher_renderer.settings,
+            paused: self.paused,
         }
-        if self.controller.forwards {
-            dir += na::Vector3::z();
+        .step(&self.controller, &self.raymarcher_renderer.scene_data, dt);
+    }
+    /// Switches between flying freely and orbiting whatever is in the centre of the screen.
+    pub fn toggle_orbit(&mut self) {
+        if self.orbit.take().is_none() {
+            let settings = &self.raymarcher_renderer.settings;
+            let data = &self.raymarcher_renderer.scene_data;
+            let distance = sdf::march(settings, data, self.camera.pos, self.camera.forward())
+                .unwrap_or(self.camera.focus_dist);
+            self.orbit = Some(Orbit::from_camera(&self.camera, distance));
+            self.movement.stop();
         }
-        if self.controller.backwards {
-            dir -= na::Vector3::z();
+    }
+
+    pub fn play_path(&mut self) {
+        self.stop_recording();
+        self.replay = None;
+        self.playback = self.path.keyframes().first().map(|k| k.time);
+        self.movement.stop();
+        self.orbit = None;
+    }
+    pub fn stop_path(&mut self) {
+        self.playback = None;
+    }
+    pub fn is_playing(&self) -> bool {
+        self.playback.is_some()
+    }
+    /// Adds the current view to the path `interval` seconds after its last keyframe.
+    pub fn record_keyframe(&mut self, interval: f32) {
+        let scene_time = self.raymarcher_renderer.settings.time;
+        self.path.record(&self.camera, scene_time, interval);
+    }
+
+    /// Starts recording input from the current view, the movement stops so it can start the same way.
+    pub fn start_recording(&mut self) {
+        self.stop_path();
+        self.replay = None;
+        self.movement.stop();
+        self.recording = Some(Recording::new(
+            self.bookmark(),
+            self.orbit,
+            self.movement,
+            self.paused,
+        ));
+    }
+    pub fn stop_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+    pub fn is_recording(&self) -> bool {
+        self.recording.is_some()
+    }
+    /// Plays back recorded input instead of the controller, using `fixed_timestep` instead of the
+    /// recorded frame times when set.
+    pub fn replay(&mut self, recording: Recording, fixed_timestep: Option<f32>) {
+        self.recording = None;
+        self.restore(&recording.start);
+        self.orbit = recording.orbit;
+        self.movement = recording.movement;
+        self.paused = recording.paused;
+        self.replay = Some(Replay::new(recording, fixed_timestep));
+    }
+    pub fn stop_replay(&mut self) {
+        if self.replay.take().is_some() {
+            self.controller = Controller::default();
         }
-        if dir.magnitude_squared() != 0.0 {
-            dir = dir.normalize();
-            dir = self
-                .camera
-                .rotation()
-                .to_homogeneous()
-                .transform_vector(&dir);
-            self.camera.pos += dir * speed * dt;
+    }
+    pub fn is_replaying(&self) -> bool {
+        self.replay.is_some()
+    }
+
+    pub fn bookmark(&self) -> Bookmark {
+        Bookmark {
+            camera: self.camera,
+            settings: self.raymarcher_renderer.settings,
         }
+    }
+    pub fn restore(&mut self, bookmark: &Bookmark) {
+        self.stop_path();
+        self.movement.stop();
+        self.orbit = None;
+        self.camera = bookmark.camera;
+        self.raymarcher_renderer.settings = bookmark.settings;
+    }
 
-        self.raymarcher_renderer.settings.time += dt;
+    /// Focuses the camera on whatever is in the centre of the screen.
+    pub fn autofocus(&mut self) {
+        let settings = &self.raymarcher_renderer.settings;
+        let data = &self.raymarcher_renderer.scene_data;
+        self.camera.focus_dist = sdf::march(settings, data, self.camera.pos, self.camera.forward())
+            .unwrap_or(settings.max_dist);
+    }
+    pub fn settings(&self) -> &SettingsUniform {
+        &self.raymarcher_renderer.settings
+    }
+    /// Lights in the scene, change them between frames to move them around.
+    pub fn lights(&self) -> &[Light] {
+        &self.raymarcher_renderer.lights
+    }
+    pub fn lights_mut(&mut self) -> &mut Vec<Light> {
+        &mut self.raymarcher_renderer.lights
+    }
+    /// Lights the scene with an equirectangular image instead of the sky, `None` goes back to the sky.
+    pub fn set_environment(&mut self, image: Option<&image::Rgb32FImage>) {
+        self.raymarcher_renderer.set_environment(
+            &self.wgpu_ctx.device,
+            &self.wgpu_ctx.queue,
+            image,
+        );
+    }
+    /// Loads an equirectangular `.hdr` image as the environment.
+    pub fn load_environment(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let image = load_hdr(path)?;
+        self.set_environment(Some(&image));
+        Ok(())
+    }
+    /// Shapes the terrain scene after a heightmap instead of noise, `None` goes back to noise.
+    pub fn set_heightmap(&mut self, heightmap: Option<Heightmap>) {
+        self.raymarcher_renderer.set_heightmap(
+            &self.wgpu_ctx.device,
+            &self.wgpu_ctx.queue,
+            heightmap,
+        );
+    }
+    /// Loads a greyscale image as the terrain's heightmap.
+    pub fn load_heightmap(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_heightmap(Some(Heightmap::load(path)?));
+        Ok(())
+    }
+    /// Adds a voxelised mesh to the scene, `None` takes it out again.
+    pub fn set_mesh(&mut self, mesh: Option<MeshSdf>) {
+        self.raymarcher_renderer
+            .set_mesh(&self.wgpu_ctx.device, &self.wgpu_ctx.queue, mesh);
+    }
+    /// Loads and voxelises an `.obj` or `.stl` file into the scene.
+    pub fn load_mesh(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_mesh(Some(MeshSdf::load(path, mesh::DEFAULT_RESOLUTION)?));
+        Ok(())
+    }
+    pub fn scene_data(&self) -> &SceneData {
+        &self.raymarcher_renderer.scene_data
+    }
+    /// Speeds up marching through empty space with a baked distance cache, `None` goes back to
+    /// working out every distance. Either way a bake that's still going is dropped.
+    pub fn set_distance_cache(&mut self, cache: Option<DistanceCache>) {
+        self.raymarcher_renderer.set_distance_cache(
+            &self.wgpu_ctx.device,
+            &self.wgpu_ctx.queue,
+            cache,
+        );
+    }
+    /// Starts baking the scene as it is now into a distance cache `max_dist` wide, centred on the
+    /// camera. It's baked on another thread and used from the first `update` after it's done,
+    /// unless the heightmap or mesh changes first.
+    pub fn bake_distance_cache(&mut self) {
+        self.raymarcher_renderer
+            .bake_distance_cache(self.camera.pos);
+    }
+    pub fn is_baking_distance_cache(&self) -> bool {
+        self.raymarcher_renderer.baking.is_some()
+    }
+    pub fn distance_cache(&self) -> Option<&DistanceCache> {
+        self.raymarcher_renderer.distance_cache.as_ref()
     }
     fn aspect(&self) -> f32 {
         self.wgpu_ctx.config.width as f32 / self.wgpu_ctx.config.height as f32
         // self.wgpu_ctx.config.height as f32 / self.wgpu_ctx.config.width as f32
     }
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        self.raymarcher_renderer.camera = self.camera.uniform(self.aspect());
-        self.raymarcher_renderer.update(&self.wgpu_ctx.queue);
+        self.raymarcher_renderer.camera = self.camera.uniform(self.aspect(), &self.stereo);
+        self.raymarcher_renderer
+            .update(&self.wgpu_ctx.device, &self.wgpu_ctx.queue);
 
         // get window's view
         let output = self.wgpu_ctx.surface.get_current_texture()?;
@@ -327,60 +993,10 @@ impl<W: HasRawWindowHandle + HasRawDisplayHandle> RayMarcher<W> {
                     label: Some("Render Encoder"),
                 });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[
-                    // This is what @location(0) in the fragment shader targets
-                    Some(wgpu::RenderPassColorAttachment {
-                        // view: &surface_view,
-                        view: &self.fullscreen_renderer.texture_view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.1,
-                                g: 0.1,
-                                b: 0.1,
-                                a: 1.0,
-                            }),
-                            store: wgpu::StoreOp::Store,
-                        },
-                    }),
-                ],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-
-            self.raymarcher_renderer.render(&mut render_pass);
-        }
-
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[
-                    // This is what @location(0) in the fragment shader targets
-                    Some(wgpu::RenderPassColorAttachment {
-                        view: &surface_view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color {
-                                r: 0.1,
-                                g: 0.1,
-                                b: 0.1,
-                                a: 1.0,
-                            }),
-                            store: wgpu::StoreOp::Store,
-                        },
-                    }),
-                ],
-                depth_stencil_attachment: None,
-                timestamp_writes: None,
-                occlusion_query_set: None,
-            });
-
-            self.fullscreen_renderer.render(&mut render_pass);
-        }
+        self.raymarcher_renderer
+            .render(&mut encoder, &self.fullscreen_renderer.texture_view, None);
+        self.fullscreen_renderer
+            .render(&mut encoder, &surface_view, None);
 
         // submit will accept anything that implements IntoIter
         self.wgpu_ctx
@@ -391,14 +1007,89 @@ impl<W: HasRawWindowHandle + HasRawDisplayHandle> RayMarcher<W> {
         Ok(())
     }
 
+    /// Copies what is currently on screen into an image.
+    pub fn screenshot(&self) -> image::RgbaImage {
+        let size = (self.wgpu_ctx.config.width, self.wgpu_ctx.config.height);
+        // the surface can be bgra, 10 bit or float, so draw it again in the format images are in
+        let output =
+            offline::create_output_texture(&self.wgpu_ctx.device, size, offline::OUTPUT_FORMAT);
+        offline::read_output(
+            &self.wgpu_ctx.device,
+            &self.wgpu_ctx.queue,
+            &self.fullscreen_renderer,
+            &output,
+        )
+    }
+
     pub fn resize(&mut self, new_size: (u32, u32)) {
         self.wgpu_ctx.resize(new_size);
 
-        self.fullscreen_renderer.resize(
-            &self.wgpu_ctx.device,
-            self.wgpu_ctx.config.format,
-            new_size,
-            self.scale,
+        self.fullscreen_renderer
+            .resize(&self.wgpu_ctx.device, new_size, self.scale);
+        self.raymarcher_renderer.reset_accumulation();
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn renderer() -> Option<(wgpu::Device, wgpu::Queue, RayMarcherRenderer)> {
+        let instance = wgpu_context::instance();
+        let (_, device, queue) = pollster::block_on(wgpu_context::request_fallback_device(
+            &instance,
+            wgpu::Features::empty(),
+        ))?;
+        let renderer = RayMarcherRenderer::new(
+            &device,
+            &queue,
+            default_settings(),
+            Camera::default().uniform(1.0, &Stereo::default()),
+        );
+        Some((device, queue, renderer))
+    }
+
+    fn wait_for_bake(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &mut RayMarcherRenderer,
+    ) {
+        while renderer.baking.is_some() {
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            renderer.finish_baking(device, queue);
+        }
+    }
+
+    #[test]
+    fn finished_bakes_are_used() {
+        let Some((device, queue, mut renderer)) = renderer() else {
+            eprintln!("no software adapter, skipping");
+            return;
+        };
+        renderer.bake_distance_cache(na::Point3::origin());
+        wait_for_bake(&device, &queue, &mut renderer);
+        assert!(renderer.distance_cache.is_some());
+    }
+
+    #[test]
+    fn changing_the_mesh_drops_a_running_bake() {
+        let Some((device, queue, mut renderer)) = renderer() else {
+            eprintln!("no software adapter, skipping");
+            return;
+        };
+        renderer.bake_distance_cache(na::Point3::origin());
+        let triangle = [
+            na::point![0.0, 0.0, 0.0],
+            na::point![1.0, 0.0, 0.0],
+            na::point![0.0, 1.0, 0.0],
+        ];
+        let mesh = MeshSdf::new(
+            &mesh::Mesh {
+                triangles: vec![triangle],
+            },
+            8,
         );
+        renderer.set_mesh(&device, &queue, Some(mesh));
+        wait_for_bake(&device, &queue, &mut renderer);
+        assert!(renderer.distance_cache.is_none());
     }
 }
@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{na, pipeline::LightUniform};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightKind {
+    /// A directional light along `sun_dir` in the settings, so it moves with the sun in the sky.
+    /// It's off while an environment replaces the sky, since that has its own sun.
+    Sun,
+    /// Infinitely far away like the sun, `direction` points towards the light.
+    Directional { direction: na::Vector3<f32> },
+    /// Falls off with the square of the distance and fades out completely at `range`.
+    Point {
+        position: na::Point3<f32>,
+        range: f32,
+    },
+    /// A point light shining along `direction`. Full strength within `inner_angle` of it, fading
+    /// out by `outer_angle`, both in radians.
+    Spot {
+        position: na::Point3<f32>,
+        direction: na::Vector3<f32>,
+        range: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    },
+    /// A glowing ball, the bigger it is the softer its shadows and highlights.
+    Sphere {
+        position: na::Point3<f32>,
+        radius: f32,
+        range: f32,
+    },
+}
+
+/// Moves a light along with the scene time, so it pauses and replays with everything else.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum LightAnimation {
+    /// Circles around `axis` through `center`, turning `speed` radians per second.
+    Orbit {
+        center: na::Point3<f32>,
+        axis: na::Vector3<f32>,
+        speed: f32,
+    },
+    /// Fades between off and full intensity `frequency` times per second.
+    Pulse { frequency: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Light {
+    pub kind: LightKind,
+    pub color: [f32; 3],
+    pub intensity: f32,
+    pub shadows: bool,
+    pub animation: Option<LightAnimation>,
+}
+impl Light {
+    fn new(kind: LightKind) -> Self {
+        Self {
+            kind,
+            color: [1.0, 1.0, 1.0],
+            intensity: 1.0,
+            shadows: true,
+            animation: None,
+        }
+    }
+    pub fn directional(direction: na::Vector3<f32>) -> Self {
+        Self::new(LightKind::Directional { direction })
+    }
+    /// The light scenes start with, a bright light from the sun.
+    pub fn sun() -> Self {
+        Self::new(LightKind::Sun).with_intensity(2.0)
+    }
+    pub fn point(position: na::Point3<f32>, range: f32) -> Self {
+        Self::new(LightKind::Point { position, range })
+    }
+    /// A spot light with a cone `angle` radians wide that fades out over its outer fifth.
+    pub fn spot(
+        position: na::Point3<f32>,
+        direction: na::Vector3<f32>,
+        range: f32,
+        angle: f32,
+    ) -> Self {
+        Self::new(LightKind::Spot {
+            position,
+            direction,
+            range,
+            inner_angle: angle * 0.4,
+            outer_angle: angle * 0.5,
+        })
+    }
+    pub fn sphere(position: na::Point3<f32>, radius: f32, range: f32) -> Self {
+        Self::new(LightKind::Sphere {
+            position,
+            radius,
+            range,
+        })
+    }
+
+    pub fn with_color(mut self, color: [f32; 3]) -> Self {
+        self.color = color;
+        self
+    }
+    pub fn with_intensity(mut self, intensity: f32) -> Self {
+        self.intensity = intensity;
+        self
+    }
+    pub fn with_shadows(mut self, shadows: bool) -> Self {
+        self.shadows = shadows;
+        self
+    }
+    pub fn with_animation(mut self, animation: LightAnimation) -> Self {
+        self.animation = Some(animation);
+        self
+    }
+
+    /// Where the light is at scene `time`.
+    pub fn at(&self, time: f32) -> Self {
+        let mut light = *self;
+        match self.animation {
+            Some(LightAnimation::Orbit {
+                center,
+                axis,
+                speed,
+            }) => {
+                let rot = na::UnitQuaternion::from_axis_angle(
+                    &na::Unit::new_normalize(axis),
+                    speed * time,
+                );
+                let orbit = |position: &mut na::Point3<f32>| {
+                    *position = center + rot * (*position - center);
+                };
+                match &mut light.kind {
+                    // the sun is moved with `sun_dir` instead
+                    LightKind::Sun => {}
+                    LightKind::Directional { direction } => *direction = rot * *direction,
+                    LightKind::Point { position, .. } | LightKind::Sphere { position, .. } => {
+                        orbit(position)
+                    }
+                    LightKind::Spot {
+                        position,
+                        direction,
+                        ..
+                    } => {
+                        orbit(position);
+                        *direction = rot * *direction;
+                    }
+                }
+            }
+            Some(LightAnimation::Pulse { frequency }) => {
+                let phase = time * frequency * std::f32::consts::TAU;
+                light.intensity *= 0.5 - 0.5 * phase.cos();
+            }
+            None => {}
+        }
+        light
+    }
+
+    pub(crate) fn uniform(&self, time: f32, sun_dir: na::Vector3<f32>) -> LightUniform {
+        let light = self.at(time);
+        let color = na::Vector3::from(light.color) * light.intensity;
+        let mut uniform = LightUniform {
+            color: color.into(),
+            shadows: light.shadows as u32,
+            ..Default::default()
+        };
+        match light.kind {
+            LightKind::Sun => {
+                uniform.kind = 0;
+                uniform.direction = sun_dir.normalize().into();
+            }
+            LightKind::Directional { direction } => {
+                uniform.kind = 0;
+                uniform.direction = direction.normalize().into();
+            }
+            LightKind::Point { position, range } => {
+                uniform.kind = 1;
+                uniform.position = position.into();
+                uniform.range = range;
+            }
+            LightKind::Spot {
+                position,
+                direction,
+                range,
+                inner_angle,
+                outer_angle,
+            } => {
+                uniform.kind = 2;
+                uniform.position = position.into();
+                uniform.direction = direction.normalize().into();
+                uniform.range = range;
+                uniform.cos_inner = inner_angle.cos();
+                uniform.cos_outer = outer_angle.cos();
+            }
+            LightKind::Sphere {
+                position,
+                radius,
+                range,
+            } => {
+                uniform.kind = 3;
+                uniform.position = position.into();
+                uniform.radius = radius;
+                uniform.range = range;
+            }
+        }
+        uniform
+    }
+}
@@ -0,0 +1,433 @@
+use crate::na;
+
+/// Grid points along the longest side of the mesh when it isn't given.
+pub const DEFAULT_RESOLUTION: u32 = 64;
+// empty cells around the mesh so the grid's edges are all outside it
+const PADDING: u32 = 2;
+// how many cells around each triangle get their distance worked out exactly
+const BAND: i64 = 1;
+
+/// A triangle mesh read from an OBJ or STL file.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Mesh {
+    pub triangles: Vec<[na::Point3<f32>; 3]>,
+}
+impl Mesh {
+    /// Reads an `.obj` or `.stl` file, going by the extension.
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let path = path.as_ref();
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase());
+        match extension.as_deref() {
+            Some("obj") => Self::parse_obj(&std::fs::read_to_string(path)?),
+            Some("stl") => Self::parse_stl(&std::fs::read(path)?),
+            _ => Err(format!("{} isn't an .obj or .stl file", path.display()).into()),
+        }
+    }
+
+    /// Only the vertices and faces are read, faces with more than three corners are split into fans.
+    pub fn parse_obj(text: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let mut vertices = Vec::new();
+        let mut triangles = Vec::new();
+        for (number, line) in text.lines().enumerate() {
+            let error = || format!("invalid line {}: {line}", number + 1);
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("v") => {
+                    let mut coord = || -> Result<f32, String> {
+                        words.next().and_then(|w| w.parse().ok()).ok_or_else(error)
+                    };
+                    vertices.push(na::point![coord()?, coord()?, coord()?]);
+                }
+                Some("f") => {
+                    let corners = words
+                        .map(|word| {
+                            // v, v/vt, v//vn or v/vt/vn, negative counts back from the last vertex
+                            let index: i64 = word
+                                .split('/')
+                                .next()
+                                .and_then(|i| i.parse().ok())
+                                .ok_or_else(error)?;
+                            let index = if index < 0 {
+                                vertices.len() as i64 + index
+                            } else {
+                                index - 1
+                            };
+                            vertices.get(index as usize).copied().ok_or_else(error)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    if corners.len() < 3 {
+                        return Err(error().into());
+                    }
+                    for i in 1..corners.len() - 1 {
+                        triangles.push([corners[0], corners[i], corners[i + 1]]);
+                    }
+                }
+                _ => {}
+            }
+        }
+        Ok(Self { triangles })
+    }
+
+    /// Reads both binary and ASCII STL.
+    pub fn parse_stl(bytes: &[u8]) -> Result<Self, Box<dyn std::error::Error>> {
+        // ascii files start with "solid" too, but binary ones are exactly this long
+        if bytes.len() >= 84 {
+            let count = u32::from_le_bytes(bytes[80..84].try_into().unwrap()) as usize;
+            if bytes.len() == 84 + count * 50 {
+                let float = |at: usize| f32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+                let point = |at: usize| na::point![float(at), float(at + 4), float(at + 8)];
+                let triangles = (0..count)
+                    .map(|i| {
+                        // after the normal
+                        let at = 84 + i * 50 + 12;
+                        [point(at), point(at + 12), point(at + 24)]
+                    })
+                    .collect();
+                return Ok(Self { triangles });
+            }
+        }
+
+        let text = std::str::from_utf8(bytes).map_err(|_| "not a binary or ascii stl file")?;
+        let mut vertices = Vec::new();
+        for line in text.lines() {
+            let mut words = line.split_whitespace();
+            if words.next() == Some("vertex") {
+                let coords: Vec<f32> = words
+                    .map(|w| w.parse())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("invalid vertex: {line}"))?;
+                if coords.len() != 3 {
+                    return Err(format!("invalid vertex: {line}").into());
+                }
+                vertices.push(na::point![coords[0], coords[1], coords[2]]);
+            }
+        }
+        if vertices.len() % 3 != 0 {
+            return Err("facets need three vertices each".into());
+        }
+        let triangles = vertices.chunks(3).map(|c| [c[0], c[1], c[2]]).collect();
+        Ok(Self { triangles })
+    }
+
+    fn bounds(&self) -> (na::Point3<f32>, na::Point3<f32>) {
+        let corners = self.triangles.iter().flatten();
+        let min = corners
+            .clone()
+            .fold(na::Point3::from(na::Vector3::repeat(f32::MAX)), |a, b| {
+                a.inf(b)
+            });
+        let max = corners.fold(na::Point3::from(na::Vector3::repeat(f32::MIN)), |a, b| {
+            a.sup(b)
+        });
+        (min, max)
+    }
+}
+
+// Ericson, Real-Time Collision Detection 5.1.5
+fn closest_point_on_triangle(
+    p: na::Point3<f32>,
+    [a, b, c]: &[na::Point3<f32>; 3],
+) -> na::Point3<f32> {
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(&ap);
+    let d2 = ac.dot(&ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return *a;
+    }
+    let bp = p - b;
+    let d3 = ab.dot(&bp);
+    let d4 = ac.dot(&bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return *b;
+    }
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+    let cp = p - c;
+    let d5 = ab.dot(&cp);
+    let d6 = ac.dot(&cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return *c;
+    }
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && d4 - d3 >= 0.0 && d5 - d6 >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+    let denom = 1.0 / (va + vb + vc);
+    a + ab * (vb * denom) + ac * (vc * denom)
+}
+
+/// Signed distances to a mesh on a grid, negative inside. The mesh is centred on the origin and
+/// scaled so its longest side is 2 units long.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MeshSdf {
+    size: [u32; 3],
+    // corner of the grid, the distances are at the centres of its cells
+    min: na::Point3<f32>,
+    cell: f32,
+    distances: Vec<f32>,
+}
+impl MeshSdf {
+    /// Voxelises `mesh` with `resolution` grid points along its longest side.
+    ///
+    /// Which side is inside is decided by counting crossings along each axis, so meshes with
+    /// small holes still work.
+    pub fn new(mesh: &Mesh, resolution: u32) -> Self {
+        let (lo, hi) = mesh.bounds();
+        let center = na::center(&lo, &hi);
+        let scale = 2.0 / (hi - lo).max().max(1e-6);
+        let triangles: Vec<_> = mesh
+            .triangles
+            .iter()
+            .map(|t| t.map(|p| na::Point3::from((p - center) * scale)))
+            // they have no sides to be closest to
+            .filter(|[a, b, c]| (b - a).cross(&(c - a)).magnitude_squared() > 0.0)
+            .collect();
+
+        let resolution = resolution.max(2 * PADDING + 2);
+        let cell = 2.0 / (resolution - 2 * PADDING) as f32;
+        let extent = (hi - lo) * scale;
+        let size = extent.map(|e| (e / cell).ceil().max(1.0) as u32 + 2 * PADDING);
+        let min = na::Point3::from(-size.cast::<f32>() * cell / 2.0);
+
+        let mut sdf = Self {
+            size: size.into(),
+            min,
+            cell,
+            distances: Vec::new(),
+        };
+        let (distances, closest) = sdf.unsigned_distances(&triangles);
+        let inside = sdf.inside(&triangles);
+        sdf.distances = distances
+            .iter()
+            .zip(&inside)
+            .zip(&closest)
+            .map(|((&d, &inside), &t)| {
+                // only without any triangles
+                let d = if t == u32::MAX { 1e9 } else { d };
+                if inside {
+                    -d
+                } else {
+                    d
+                }
+            })
+            .collect();
+        sdf
+    }
+    /// Loads and voxelises an `.obj` or `.stl` file.
+    pub fn load(
+        path: impl AsRef<std::path::Path>,
+        resolution: u32,
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        let mesh = Mesh::load(path)?;
+        if mesh.triangles.is_empty() {
+            return Err("the mesh has no triangles".into());
+        }
+        Ok(Self::new(&mesh, resolution))
+    }
+
+    /// Grid points along each axis.
+    pub fn size(&self) -> [u32; 3] {
+        self.size
+    }
+    /// Corner and size of the box the grid covers.
+    pub(crate) fn bounds(&self) -> (na::Point3<f32>, na::Vector3<f32>) {
+        let size = na::Vector3::from(self.size).cast::<f32>() * self.cell;
+        (self.min, size)
+    }
+    pub(crate) fn distances(&self) -> &[f32] {
+        &self.distances
+    }
+
+    fn index(&self, x: usize, y: usize, z: usize) -> usize {
+        (z * self.size[1] as usize + y) * self.size[0] as usize + x
+    }
+    fn center(&self, x: usize, y: usize, z: usize) -> na::Point3<f32> {
+        self.min + na::vector![x as f32 + 0.5, y as f32 + 0.5, z as f32 + 0.5] * self.cell
+    }
+
+    // exact distances close to the triangles, carried out to the rest of the grid by sweeping
+    // across it and checking each cell against the closest triangles of its neighbours
+    fn unsigned_distances(&self, triangles: &[[na::Point3<f32>; 3]]) -> (Vec<f32>, Vec<u32>) {
+        let [sx, sy, sz] = self.size.map(|s| s as usize);
+        let mut distances = vec![f32::MAX; sx * sy * sz];
+        let mut closest = vec![u32::MAX; sx * sy * sz];
+
+        let cell_of = |p: f32, axis: usize| ((p - self.min[axis]) / self.cell - 0.5).floor() as i64;
+        for (t, triangle) in triangles.iter().enumerate() {
+            let lo = triangle[0].inf(&triangle[1]).inf(&triangle[2]);
+            let hi = triangle[0].sup(&triangle[1]).sup(&triangle[2]);
+            let range = |axis: usize| {
+                let size = self.size[axis] as i64;
+                let from = (cell_of(lo[axis], axis) - BAND).clamp(0, size - 1);
+                let to = (cell_of(hi[axis], axis) + 1 + BAND).clamp(0, size - 1);
+                from as usize..=to as usize
+            };
+            for z in range(2) {
+                for y in range(1) {
+                    for x in range(0) {
+                        let p = self.center(x, y, z);
+                        let d = (p - closest_point_on_triangle(p, triangle)).magnitude();
+                        let i = self.index(x, y, z);
+                        if d < distances[i] {
+                            distances[i] = d;
+                            closest[i] = t as u32;
+                        }
+                    }
+                }
+            }
+        }
+
+        let ordered = |size: usize, forwards: bool| -> Vec<usize> {
+            if forwards {
+                (0..size).collect()
+            } else {
+                (0..size).rev().collect()
+            }
+        };
+        for _ in 0..2 {
+            for direction in 0..8 {
+                let step = [direction & 1 == 0, direction & 2 == 0, direction & 4 == 0];
+                let back = |i: usize, forwards: bool, size: usize| {
+                    if forwards {
+                        i.checked_sub(1)
+                    } else {
+                        Some(i + 1).filter(|&i| i < size)
+                    }
+                };
+                for z in ordered(sz, step[2]) {
+                    for y in ordered(sy, step[1]) {
+                        for x in ordered(sx, step[0]) {
+                            let i = self.index(x, y, z);
+                            let neighbours = [
+                                back(x, step[0], sx).map(|x| self.index(x, y, z)),
+                                back(y, step[1], sy).map(|y| self.index(x, y, z)),
+                                back(z, step[2], sz).map(|z| self.index(x, y, z)),
+                            ];
+                            for n in neighbours.into_iter().flatten() {
+                                let t = closest[n];
+                                if t == u32::MAX || t == closest[i] {
+                                    continue;
+                                }
+                                let p = self.center(x, y, z);
+                                let d = (p - closest_point_on_triangle(p, &triangles[t as usize]))
+                                    .magnitude();
+                                if d < distances[i] {
+                                    distances[i] = d;
+                                    closest[i] = t;
+                                }
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        (distances, closest)
+    }
+
+    // a cell is inside if lines through it along at least two of the axes cross the mesh an odd
+    // number of times before reaching it
+    fn inside(&self, triangles: &[[na::Point3<f32>; 3]]) -> Vec<bool> {
+        let mut votes = vec![0u8; self.size.iter().map(|&s| s as usize).product()];
+        for axis in 0..3 {
+            let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+            let (su, sv, sa) = (
+                self.size[u] as usize,
+                self.size[v] as usize,
+                self.size[axis] as usize,
+            );
+            // crossings before each cell along every line
+            let mut crossings = vec![0u32; su * sv * (sa + 1)];
+            for [a, b, c] in triangles {
+                let lo = a.inf(b).inf(c);
+                let hi = a.sup(b).sup(c);
+                let lines = |w: usize, size: usize| {
+                    let from = ((lo[w] - self.min[w]) / self.cell - 0.5).ceil().max(0.0) as usize;
+                    let to = ((hi[w] - self.min[w]) / self.cell - 0.5).floor();
+                    from..(to + 1.0).clamp(0.0, size as f32) as usize
+                };
+                for j in lines(v, sv) {
+                    for i in lines(u, su) {
+                        // nudged so lines don't run exactly along the edges between triangles
+                        let pu = self.min[u] + (i as f32 + 0.5 + 1.3e-4) * self.cell;
+                        let pv = self.min[v] + (j as f32 + 0.5 + 2.9e-4) * self.cell;
+                        let edge = |p: &na::Point3<f32>, q: &na::Point3<f32>| {
+                            (q[u] - p[u]) * (pv - p[v]) - (q[v] - p[v]) * (pu - p[u])
+                        };
+                        let (wa, wb, wc) = (edge(b, c), edge(c, a), edge(a, b));
+                        let all_positive = wa >= 0.0 && wb >= 0.0 && wc >= 0.0;
+                        let all_negative = wa <= 0.0 && wb <= 0.0 && wc <= 0.0;
+                        let total = wa + wb + wc;
+                        if !(all_positive || all_negative) || total == 0.0 {
+                            continue;
+                        }
+                        let depth = (wa * a[axis] + wb * b[axis] + wc * c[axis]) / total;
+                        let cell = ((depth - self.min[axis]) / self.cell - 0.5).floor() + 1.0;
+                        let cell = cell.clamp(0.0, sa as f32) as usize;
+                        crossings[(j * su + i) * (sa + 1) + cell] += 1;
+                    }
+                }
+            }
+            for j in 0..sv {
+                for i in 0..su {
+                    let line = &crossings[(j * su + i) * (sa + 1)..][..sa];
+                    let mut count = 0;
+                    for (k, crossed) in line.iter().enumerate() {
+                        count += crossed;
+                        let mut cell = [0; 3];
+                        (cell[axis], cell[u], cell[v]) = (k, i, j);
+                        if count % 2 == 1 {
+                            votes[self.index(cell[0], cell[1], cell[2])] += 1;
+                        }
+                    }
+                }
+            }
+        }
+        votes.into_iter().map(|v| v >= 2).collect()
+    }
+
+    fn grid(&self, x: i64, y: i64, z: i64) -> f32 {
+        let clamp = |i: i64, axis: usize| i.clamp(0, self.size[axis] as i64 - 1) as usize;
+        self.distances[self.index(clamp(x, 0), clamp(y, 1), clamp(z, 2))]
+    }
+    // blends the eight closest grid points like the shader's trilinear filtering
+    fn sample(&self, p: na::Point3<f32>) -> f32 {
+        let t = (p - self.min) / self.cell - na::Vector3::repeat(0.5);
+        let i = t.map(f32::floor);
+        let f = t - i;
+        let (x, y, z) = (i.x as i64, i.y as i64, i.z as i64);
+
+        let mix = |a: f32, b: f32, t: f32| a * (1.0 - t) + b * t;
+        let y0 = mix(self.grid(x, y, z), self.grid(x + 1, y, z), f.x);
+        let y1 = mix(self.grid(x, y + 1, z), self.grid(x + 1, y + 1, z), f.x);
+        let y2 = mix(self.grid(x, y, z + 1), self.grid(x + 1, y, z + 1), f.x);
+        let y3 = mix(
+            self.grid(x, y + 1, z + 1),
+            self.grid(x + 1, y + 1, z + 1),
+            f.x,
+        );
+        mix(mix(y0, y1, f.y), mix(y2, y3, f.y), f.z)
+    }
+    /// Distance from `p` to the mesh, blended between grid points inside the grid.
+    ///
+    /// Outside it, the closest point of the grid is the closest way in, so the mesh is at least
+    /// as far as the distance to that point and from there to the mesh at right angles.
+    pub fn distance(&self, p: na::Point3<f32>) -> f32 {
+        let (min, size) = self.bounds();
+        let inside = p.sup(&min).inf(&(min + size));
+        let d = self.sample(inside);
+        let outside = (p - inside).magnitude();
+        if outside > 0.0 {
+            outside.hypot(d.max(0.0))
+        } else {
+            d
+        }
+    }
+}
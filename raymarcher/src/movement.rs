@@ -0,0 +1,205 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{na, sdf, Camera, Controller, SceneData, SettingsUniform};
+
+// Pushing out of the surface is repeated because the distance estimators underestimate.
+const COLLISION_ITERATIONS: usize = 4;
+// Most steps a single move is broken into, whatever is left of the step after that is dropped.
+const COLLISION_SUBSTEPS: usize = 16;
+
+/// Moves the camera slower the closer it is to the surface so it's possible to dive into the fractals.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Movement {
+    /// Speed per unit of distance to the surface.
+    pub speed: f32,
+    pub min_speed: f32,
+    pub max_speed: f32,
+    /// How much `Controller::speed` can speed up or slow down movement.
+    pub speed_range: f32,
+    /// Radians per second when turning as fast as the controller allows.
+    pub look_speed: f32,
+    /// Radians per pixel of mouse movement.
+    pub mouse_sensitivity: f32,
+    /// Orbit distances per pixel of mouse movement when panning.
+    pub pan_sensitivity: f32,
+    /// How quickly the camera gets up to speed when moving.
+    pub acceleration: f32,
+    /// How quickly the camera slows down when not moving.
+    pub damping: f32,
+
+    /// Keeps the camera from getting closer than `collision_radius` to the surface.
+    pub collision: bool,
+    pub collision_radius: f32,
+
+    #[serde(skip)]
+    velocity: na::Vector3<f32>,
+}
+impl Default for Movement {
+    fn default() -> Self {
+        Self {
+            speed: 1.0,
+            min_speed: 0.0001,
+            max_speed: 2.0,
+            speed_range: 8.0,
+            look_speed: 2.0,
+            mouse_sensitivity: 0.005,
+            pan_sensitivity: 0.002,
+            acceleration: 10.0,
+            damping: 5.0,
+            collision: true,
+            collision_radius: 0.002,
+            velocity: na::Vector3::zeros(),
+        }
+    }
+}
+impl Movement {
+    pub fn velocity(&self) -> na::Vector3<f32> {
+        self.velocity
+    }
+    pub fn stop(&mut self) {
+        self.velocity = na::Vector3::zeros();
+    }
+
+    pub fn update(
+        &mut self,
+        camera: &mut Camera,
+        controller: &Controller,
+        settings: &SettingsUniform,
+        data: &SceneData,
+        dt: f32,
+    ) {
+        let dir = controller.direction();
+        let dist = sdf::de(settings, data, camera.pos).abs();
+        let speed = (dist * self.speed).clamp(self.min_speed, self.max_speed)
+            * self.speed_range.powf(controller.speed.clamp(-1.0, 1.0));
+
+        let target = camera.rot * dir * speed;
+        let rate = if dir.magnitude_squared() != 0.0 {
+            self.acceleration
+        } else {
+            self.damping
+        };
+        // exponential smoothing so the result doesn't depend on the frame rate
+        self.velocity += (target - self.velocity) * (1.0 - (-rate * dt).exp());
+
+        let mut step = self.velocity * dt;
+        if self.collision {
+            step = self.collide(camera.pos, step, settings, data);
+        }
+        camera.pos += step;
+    }
+
+    /// Moves along the step in pieces no longer than the distance to the surface, like marching
+    /// a ray, so a fast step can't pass through a wall thinner than itself.
+    fn collide(
+        &mut self,
+        pos: na::Point3<f32>,
+        step: na::Vector3<f32>,
+        settings: &SettingsUniform,
+        data: &SceneData,
+    ) -> na::Vector3<f32> {
+        let mut moved = na::Vector3::zeros();
+        let mut remaining = step;
+        for _ in 0..COLLISION_SUBSTEPS {
+            let length = remaining.magnitude();
+            if length == 0.0 {
+                break;
+            }
+            let at = pos + moved;
+            let reach = sdf::de(settings, data, at).max(self.collision_radius);
+            let part = remaining * (reach / length).min(1.0);
+            remaining -= part;
+            moved += self.slide(at, part, settings, data);
+        }
+        moved
+    }
+
+    /// Slides the step along the surface instead of going into it.
+    fn slide(
+        &mut self,
+        pos: na::Point3<f32>,
+        mut step: na::Vector3<f32>,
+        settings: &SettingsUniform,
+        data: &SceneData,
+    ) -> na::Vector3<f32> {
+        let radius = self.collision_radius;
+        let h = radius / 10.0;
+
+        let next = pos + step;
+        if sdf::de(settings, data, next) < radius {
+            let n = sdf::normal(settings, data, next, h);
+            let into = step.dot(&n);
+            if into < 0.0 {
+                step -= n * into;
+                self.velocity -= n * self.velocity.dot(&n).min(0.0);
+            }
+        }
+
+        for _ in 0..COLLISION_ITERATIONS {
+            let next = pos + step;
+            let dist = sdf::de(settings, data, next);
+            if dist >= radius {
+                break;
+            }
+            step += sdf::normal(settings, data, next, h) * (radius - dist);
+        }
+        step
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forwards() -> Controller {
+        Controller {
+            translation: na::Vector3::z(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn flying_through_the_origin() {
+        let settings = crate::default_settings();
+        let mut movement = Movement {
+            collision: false,
+            ..Default::default()
+        };
+        let mut camera = Camera {
+            pos: na::Point3::origin(),
+            ..Default::default()
+        };
+        for _ in 0..10 {
+            movement.update(
+                &mut camera,
+                &forwards(),
+                &settings,
+                &SceneData::default(),
+                0.1,
+            );
+        }
+        assert!(movement.velocity().iter().all(|v| v.is_finite()));
+        assert!(camera.pos.iter().all(|p| p.is_finite()));
+    }
+
+    #[test]
+    fn fast_steps_dont_pass_through_walls() {
+        let settings = SettingsUniform {
+            scene: 3,
+            ..crate::default_settings()
+        };
+        let mut movement = Movement::default();
+        // far enough to land on the other side of the sphere
+        let start = na::point![0.0, 0.0, -3.0];
+        let step = movement.collide(
+            start,
+            na::vector![0.0, 0.0, 6.0],
+            &settings,
+            &SceneData::default(),
+        );
+        let end = start + step;
+        assert!(end.z < -1.0, "went through the sphere to {end}");
+        assert!(sdf::de(&settings, &SceneData::default(), end) >= movement.collision_radius * 0.99);
+    }
+}
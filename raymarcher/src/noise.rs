@@ -0,0 +1,256 @@
+// CPU side versions of the noise in noise.wgsl.
+// Keep these in sync with the shader.
+
+use serde::{Deserialize, Serialize};
+
+use crate::na;
+
+/// Which noise to build fbm out of, same numbers as in noise.wgsl.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Noise {
+    /// Random values at the corners of a grid blended together, -1 to 1.
+    #[default]
+    Value,
+    /// Perlin's gradient noise, around -1 to 1.
+    Perlin,
+    /// Gradient noise on a triangular grid, around -1 to 1 without Perlin's grid artifacts.
+    Simplex,
+    /// Distance to the closest of points scattered one per cell, 0 to a little over 1.
+    Worley,
+}
+impl Noise {
+    pub fn sample2(&self, p: na::Vector2<f32>) -> f32 {
+        match self {
+            Noise::Value => value2(p),
+            Noise::Perlin => perlin2(p),
+            Noise::Simplex => simplex2(p),
+            Noise::Worley => worley2(p),
+        }
+    }
+    pub fn sample3(&self, p: na::Vector3<f32>) -> f32 {
+        match self {
+            Noise::Value => value3(p),
+            Noise::Perlin => perlin3(p),
+            Noise::Simplex => simplex3(p),
+            Noise::Worley => worley3(p),
+        }
+    }
+}
+
+// pcg hash
+fn hash(x: u32) -> u32 {
+    let state = x.wrapping_mul(747796405).wrapping_add(2891336453);
+    let word = ((state >> ((state >> 28) + 4)) ^ state).wrapping_mul(277803737);
+    (word >> 22) ^ word
+}
+fn hash2(c: na::Vector2<i32>) -> u32 {
+    hash((c.x as u32).wrapping_add(hash(c.y as u32)))
+}
+fn hash3(c: na::Vector3<i32>) -> u32 {
+    hash((c.x as u32).wrapping_add(hash((c.y as u32).wrapping_add(hash(c.z as u32)))))
+}
+fn unit(h: u32) -> f32 {
+    h as f32 / 4294967295.0
+}
+
+fn mix(a: f32, b: f32, t: f32) -> f32 {
+    a * (1.0 - t) + b * t
+}
+fn cell2(p: na::Vector2<f32>) -> (na::Vector2<i32>, na::Vector2<f32>) {
+    let i = p.map(f32::floor);
+    (i.map(|i| i as i32), p - i)
+}
+fn cell3(p: na::Vector3<f32>) -> (na::Vector3<i32>, na::Vector3<f32>) {
+    let i = p.map(f32::floor);
+    (i.map(|i| i as i32), p - i)
+}
+
+pub fn value2(p: na::Vector2<f32>) -> f32 {
+    let (c, f) = cell2(p);
+    let u = f.map(|f| f * f * (3.0 - 2.0 * f));
+    let at = |x, y| unit(hash2(c + na::vector![x, y]));
+
+    let x0 = mix(at(0, 0), at(1, 0), u.x);
+    let x1 = mix(at(0, 1), at(1, 1), u.x);
+    mix(x0, x1, u.y) * 2.0 - 1.0
+}
+pub fn value3(p: na::Vector3<f32>) -> f32 {
+    let (c, f) = cell3(p);
+    let u = f.map(|f| f * f * (3.0 - 2.0 * f));
+    let at = |x, y, z| unit(hash3(c + na::vector![x, y, z]));
+
+    let x00 = mix(at(0, 0, 0), at(1, 0, 0), u.x);
+    let x10 = mix(at(0, 1, 0), at(1, 1, 0), u.x);
+    let x01 = mix(at(0, 0, 1), at(1, 0, 1), u.x);
+    let x11 = mix(at(0, 1, 1), at(1, 1, 1), u.x);
+    mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z) * 2.0 - 1.0
+}
+/// Steepest slope of [`value3`].
+pub(crate) const VALUE_SLOPE: f32 = 5.196_152;
+
+fn gradient2(h: u32, f: na::Vector2<f32>) -> f32 {
+    let k = h & 7;
+    let sx = if k & 1 == 0 { 1.0 } else { -1.0 };
+    let sy = if k & 2 == 0 { 1.0 } else { -1.0 };
+    if k < 4 {
+        (sx * f.x + sy * f.y) * std::f32::consts::FRAC_1_SQRT_2
+    } else if k & 2 == 0 {
+        sx * f.x
+    } else {
+        sx * f.y
+    }
+}
+fn gradient3(h: u32, f: na::Vector3<f32>) -> f32 {
+    let k = h & 15;
+    let u = if k < 8 { f.x } else { f.y };
+    let v = if k < 4 {
+        f.y
+    } else if k == 12 || k == 14 {
+        f.x
+    } else {
+        f.z
+    };
+    (if k & 1 == 0 { u } else { -u }) + (if k & 2 == 0 { v } else { -v })
+}
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+pub fn perlin2(p: na::Vector2<f32>) -> f32 {
+    let (c, f) = cell2(p);
+    let at = |x, y| {
+        let corner = na::vector![x, y];
+        gradient2(hash2(c + corner), f - corner.map(|x| x as f32))
+    };
+
+    let u = fade(f.x);
+    let x0 = mix(at(0, 0), at(1, 0), u);
+    let x1 = mix(at(0, 1), at(1, 1), u);
+    mix(x0, x1, fade(f.y)) * std::f32::consts::SQRT_2
+}
+/// A little over the steepest slope of [`perlin2`] found by sampling it.
+pub(crate) const PERLIN_SLOPE: f32 = 3.0;
+pub fn perlin3(p: na::Vector3<f32>) -> f32 {
+    let (c, f) = cell3(p);
+    let at = |x, y, z| {
+        let corner = na::vector![x, y, z];
+        gradient3(hash3(c + corner), f - corner.map(|x| x as f32))
+    };
+
+    let u = fade(f.x);
+    let v = fade(f.y);
+    let y0 = mix(
+        mix(at(0, 0, 0), at(1, 0, 0), u),
+        mix(at(0, 1, 0), at(1, 1, 0), u),
+        v,
+    );
+    let y1 = mix(
+        mix(at(0, 0, 1), at(1, 0, 1), u),
+        mix(at(0, 1, 1), at(1, 1, 1), u),
+        v,
+    );
+    mix(y0, y1, fade(f.z))
+}
+
+fn simplex_corner2(c: na::Vector2<i32>, x: na::Vector2<f32>) -> f32 {
+    let t = (0.5 - x.dot(&x)).max(0.0);
+    t * t * t * t * gradient2(hash2(c), x)
+}
+pub fn simplex2(p: na::Vector2<f32>) -> f32 {
+    let f2 = 0.366_025_4;
+    let g2 = 0.211_324_87;
+
+    let i = p.add_scalar((p.x + p.y) * f2).map(f32::floor);
+    let x0 = p - i.add_scalar(-(i.x + i.y) * g2);
+    let o = if x0.x > x0.y {
+        na::vector![1.0, 0.0]
+    } else {
+        na::vector![0.0, 1.0]
+    };
+    let x1 = (x0 - o).add_scalar(g2);
+    let x2 = x0.add_scalar(-1.0 + 2.0 * g2);
+
+    let c = i.map(|i| i as i32);
+    let n = simplex_corner2(c, x0)
+        + simplex_corner2(c + o.map(|o| o as i32), x1)
+        + simplex_corner2(c + na::vector![1, 1], x2);
+    n * 99.2
+}
+fn simplex_corner3(c: na::Vector3<i32>, x: na::Vector3<f32>) -> f32 {
+    let t = (0.6 - x.dot(&x)).max(0.0);
+    t * t * t * t * gradient3(hash3(c), x)
+}
+pub fn simplex3(p: na::Vector3<f32>) -> f32 {
+    let f3 = 1.0 / 3.0;
+    let g3 = 1.0 / 6.0;
+
+    let i = p.add_scalar((p.x + p.y + p.z) * f3).map(f32::floor);
+    let x0 = p - i.add_scalar(-(i.x + i.y + i.z) * g3);
+    // which of the six tetrahedra in the cube
+    let ge = x0.zip_map(&x0.yzx(), |a, b| if a >= b { 1.0 } else { 0.0 });
+    let le = ge.map(|g| 1.0 - g).zxy();
+    let o1 = ge.inf(&le);
+    let o2 = ge.sup(&le);
+    let x1 = (x0 - o1).add_scalar(g3);
+    let x2 = (x0 - o2).add_scalar(2.0 * g3);
+    let x3 = x0.add_scalar(-1.0 + 3.0 * g3);
+
+    let c = i.map(|i| i as i32);
+    let n = simplex_corner3(c, x0)
+        + simplex_corner3(c + o1.map(|o| o as i32), x1)
+        + simplex_corner3(c + o2.map(|o| o as i32), x2)
+        + simplex_corner3(c + na::vector![1, 1, 1], x3);
+    n * 32.0
+}
+
+pub fn worley2(p: na::Vector2<f32>) -> f32 {
+    let (c, f) = cell2(p);
+    let mut closest = 8.0_f32;
+    for y in -1..=1 {
+        for x in -1..=1 {
+            let h = hash2(c + na::vector![x, y]);
+            let point = na::vector![unit(h), unit(hash(h))];
+            let d = na::vector![x as f32, y as f32] + point - f;
+            closest = closest.min(d.dot(&d));
+        }
+    }
+    closest.sqrt()
+}
+pub fn worley3(p: na::Vector3<f32>) -> f32 {
+    let (c, f) = cell3(p);
+    let mut closest = 8.0_f32;
+    for z in -1..=1 {
+        for y in -1..=1 {
+            for x in -1..=1 {
+                let h = hash3(c + na::vector![x, y, z]);
+                let point = na::vector![unit(h), unit(hash(h)), unit(hash(hash(h)))];
+                let d = na::vector![x as f32, y as f32, z as f32] + point - f;
+                closest = closest.min(d.dot(&d));
+            }
+        }
+    }
+    closest.sqrt()
+}
+
+/// Octaves of `noise`, each `lacunarity` times finer and `gain` times weaker than the last,
+/// scaled back to the range of the noise.
+pub fn fbm2(noise: Noise, p: na::Vector2<f32>, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    let (mut sum, mut total, mut amplitude, mut q) = (0.0, 0.0, 1.0, p);
+    for _ in 0..octaves {
+        sum += amplitude * noise.sample2(q);
+        total += amplitude;
+        amplitude *= gain;
+        q *= lacunarity;
+    }
+    sum / f32::max(total, 1e-6)
+}
+pub fn fbm3(noise: Noise, p: na::Vector3<f32>, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    let (mut sum, mut total, mut amplitude, mut q) = (0.0, 0.0, 1.0, p);
+    for _ in 0..octaves {
+        sum += amplitude * noise.sample3(q);
+        total += amplitude;
+        amplitude *= gain;
+        q *= lacunarity;
+    }
+    sum / f32::max(total, 1e-6)
+}
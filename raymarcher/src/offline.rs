@@ -0,0 +1,403 @@
+use crate::{
+    benchmark::FrameTime, default_settings, distance_cache::DistanceCache, heightmap::Heightmap,
+    light::Light, mesh, na, wgpu_context, Camera, FullscreenRenderer, Projection,
+    RayMarcherRenderer, SceneData, SettingsUniform, Stereo,
+};
+
+// Not srgb so images look the same as they do on screen.
+pub(crate) const OUTPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8Unorm;
+
+pub(crate) fn create_output_texture(
+    device: &wgpu::Device,
+    size: (u32, u32),
+    format: wgpu::TextureFormat,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Output Texture"),
+        size: wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    })
+}
+
+/// Cubemap faces in +X, -X, +Y, -Y, +Z, -Z order as (forward, up).
+fn cubemap_faces() -> [(na::Vector3<f32>, na::Vector3<f32>); 6] {
+    [
+        (na::Vector3::x(), na::Vector3::y()),
+        (-na::Vector3::x(), na::Vector3::y()),
+        (na::Vector3::y(), -na::Vector3::z()),
+        (-na::Vector3::y(), na::Vector3::z()),
+        (na::Vector3::z(), na::Vector3::y()),
+        (-na::Vector3::z(), na::Vector3::y()),
+    ]
+}
+
+// Start and end of the raymarcher and fullscreen passes.
+const TIMESTAMP_COUNT: u32 = 4;
+
+struct Timestamps {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    read_buffer: wgpu::Buffer,
+    // nanoseconds per tick
+    period: f32,
+}
+impl Timestamps {
+    fn new(device: &wgpu::Device, queue: &wgpu::Queue) -> Self {
+        let size = TIMESTAMP_COUNT as wgpu::BufferAddress * 8;
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("Timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: TIMESTAMP_COUNT,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Resolve Buffer"),
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            read_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("Timestamp Read Buffer"),
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            period: queue.get_timestamp_period(),
+        }
+    }
+    fn pass(&self, pass: u32) -> wgpu::RenderPassTimestampWrites<'_> {
+        wgpu::RenderPassTimestampWrites {
+            query_set: &self.query_set,
+            beginning_of_pass_write_index: Some(pass * 2),
+            end_of_pass_write_index: Some(pass * 2 + 1),
+        }
+    }
+    fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..TIMESTAMP_COUNT, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.read_buffer,
+            0,
+            self.read_buffer.size(),
+        );
+    }
+    /// Milliseconds each pass took.
+    fn read(&self, device: &wgpu::Device) -> [f32; TIMESTAMP_COUNT as usize / 2] {
+        let slice = self.read_buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+        device.poll(wgpu::Maintain::Wait);
+
+        let data = slice.get_mapped_range();
+        let ticks: &[u64] = bytemuck::cast_slice(&data);
+        let times = [0, 1].map(|pass| {
+            let ticks = ticks[pass * 2 + 1].wrapping_sub(ticks[pass * 2]);
+            ticks as f32 * self.period / 1_000_000.0
+        });
+        drop(data);
+        self.read_buffer.unmap();
+        times
+    }
+}
+
+/// Renders to images instead of a window.
+pub struct OfflineRenderer {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub stereo: Stereo,
+
+    adapter_info: wgpu::AdapterInfo,
+    size: (u32, u32),
+    output: wgpu::Texture,
+    // only there when the adapter supports timestamp queries
+    timestamps: Option<Timestamps>,
+
+    raymarcher_renderer: RayMarcherRenderer,
+    fullscreen_renderer: FullscreenRenderer,
+}
+impl OfflineRenderer {
+    pub async fn new(size: (u32, u32)) -> Self {
+        let instance = wgpu_context::instance();
+        let (adapter, device, queue) =
+            wgpu_context::request_device(&instance, None, wgpu::Features::TIMESTAMP_QUERY).await;
+        Self::with_device(adapter, device, queue, size)
+    }
+    /// Renders on a software adapter so images are the same everywhere, `None` if there isn't one.
+    pub async fn new_fallback(size: (u32, u32)) -> Option<Self> {
+        let instance = wgpu_context::instance();
+        let (adapter, device, queue) =
+            wgpu_context::request_fallback_device(&instance, wgpu::Features::TIMESTAMP_QUERY)
+                .await?;
+        Some(Self::with_device(adapter, device, queue, size))
+    }
+    fn with_device(
+        adapter: wgpu::Adapter,
+        device: wgpu::Device,
+        queue: wgpu::Queue,
+        size: (u32, u32),
+    ) -> Self {
+        let timestamps = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| Timestamps::new(&device, &queue));
+
+        let aspect = size.0 as f32 / size.1 as f32;
+        let stereo = Stereo::default();
+        let raymarcher_renderer = RayMarcherRenderer::new(
+            &device,
+            &queue,
+            default_settings(),
+            Camera::default().uniform(aspect, &stereo),
+        );
+        let fullscreen_renderer = FullscreenRenderer::new(&device, OUTPUT_FORMAT, size, 1.0);
+        let output = create_output_texture(&device, size, OUTPUT_FORMAT);
+
+        Self {
+            device,
+            queue,
+            stereo,
+            adapter_info: adapter.get_info(),
+            size,
+            output,
+            timestamps,
+            raymarcher_renderer,
+            fullscreen_renderer,
+        }
+    }
+    pub fn settings(&self) -> &SettingsUniform {
+        &self.raymarcher_renderer.settings
+    }
+    pub fn settings_mut(&mut self) -> &mut SettingsUniform {
+        &mut self.raymarcher_renderer.settings
+    }
+    pub fn lights(&self) -> &[Light] {
+        &self.raymarcher_renderer.lights
+    }
+    pub fn lights_mut(&mut self) -> &mut Vec<Light> {
+        &mut self.raymarcher_renderer.lights
+    }
+    /// Lights the scene with an equirectangular image instead of the sky, `None` goes back to the sky.
+    pub fn set_environment(&mut self, image: Option<&image::Rgb32FImage>) {
+        self.raymarcher_renderer
+            .set_environment(&self.device, &self.queue, image);
+    }
+    /// Loads an equirectangular `.hdr` image as the environment.
+    pub fn load_environment(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let image = crate::load_hdr(path)?;
+        self.set_environment(Some(&image));
+        Ok(())
+    }
+    /// Shapes the terrain scene after a heightmap instead of noise, `None` goes back to noise.
+    pub fn set_heightmap(&mut self, heightmap: Option<Heightmap>) {
+        self.raymarcher_renderer
+            .set_heightmap(&self.device, &self.queue, heightmap);
+    }
+    /// Loads a greyscale image as the terrain's heightmap.
+    pub fn load_heightmap(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_heightmap(Some(Heightmap::load(path)?));
+        Ok(())
+    }
+    /// Adds a voxelised mesh to the scene, `None` takes it out again.
+    pub fn set_mesh(&mut self, mesh: Option<mesh::MeshSdf>) {
+        self.raymarcher_renderer
+            .set_mesh(&self.device, &self.queue, mesh);
+    }
+    /// Loads and voxelises an `.obj` or `.stl` file into the scene.
+    pub fn load_mesh(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_mesh(Some(mesh::MeshSdf::load(path, mesh::DEFAULT_RESOLUTION)?));
+        Ok(())
+    }
+    pub fn scene_data(&self) -> &SceneData {
+        &self.raymarcher_renderer.scene_data
+    }
+    /// Speeds up marching through empty space with a baked distance cache, `None` goes back to
+    /// working out every distance.
+    pub fn set_distance_cache(&mut self, cache: Option<DistanceCache>) {
+        self.raymarcher_renderer
+            .set_distance_cache(&self.device, &self.queue, cache);
+    }
+    pub fn distance_cache(&self) -> Option<&DistanceCache> {
+        self.raymarcher_renderer.distance_cache.as_ref()
+    }
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+    pub fn size(&self) -> (u32, u32) {
+        self.size
+    }
+    pub fn resize(&mut self, new_size: (u32, u32)) {
+        if new_size.0 > 0 && new_size.1 > 0 && new_size != self.size {
+            self.size = new_size;
+            self.output = create_output_texture(&self.device, new_size, OUTPUT_FORMAT);
+            self.fullscreen_renderer.resize(&self.device, new_size, 1.0);
+            self.raymarcher_renderer.reset_accumulation();
+        }
+    }
+
+    /// Renders `samples` samples per pixel. More than one is only useful with depth of field.
+    /// Every call starts again from nothing, so rendering the same view twice gives the same image.
+    pub fn render(&mut self, camera: &Camera, samples: u32) -> image::RgbaImage {
+        self.raymarcher_renderer.reset_accumulation();
+        let aspect = self.size.0 as f32 / self.size.1 as f32;
+        self.raymarcher_renderer.camera = camera.uniform(aspect, &self.stereo);
+
+        for _ in 0..samples.max(1) {
+            self.raymarcher_renderer.update(&self.device, &self.queue);
+
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Offline Render Encoder"),
+                });
+            self.raymarcher_renderer.render(
+                &mut encoder,
+                &self.fullscreen_renderer.texture_view,
+                None,
+            );
+            self.queue.submit(std::iter::once(encoder.finish()));
+        }
+
+        read_output(
+            &self.device,
+            &self.queue,
+            &self.fullscreen_renderer,
+            &self.output,
+        )
+    }
+
+    /// Renders a single sample into the output the way a window would, and measures how long it
+    /// took. Pass times are only measured if the adapter supports timestamp queries.
+    pub fn render_timed(&mut self, camera: &Camera) -> FrameTime {
+        let aspect = self.size.0 as f32 / self.size.1 as f32;
+        self.raymarcher_renderer.camera = camera.uniform(aspect, &self.stereo);
+        self.raymarcher_renderer.update(&self.device, &self.queue);
+        let output_view = self
+            .output
+            .create_view(&wgpu::TextureViewDescriptor::default());
+
+        let start = std::time::Instant::now();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Timed Render Encoder"),
+            });
+        let timestamps = self.timestamps.as_ref();
+        self.raymarcher_renderer.render(
+            &mut encoder,
+            &self.fullscreen_renderer.texture_view,
+            timestamps.map(|t| t.pass(0)),
+        );
+        self.fullscreen_renderer
+            .render(&mut encoder, &output_view, timestamps.map(|t| t.pass(1)));
+        if let Some(timestamps) = timestamps {
+            timestamps.resolve(&mut encoder);
+        }
+        self.queue.submit(std::iter::once(encoder.finish()));
+        self.device.poll(wgpu::Maintain::Wait);
+        let total = start.elapsed().as_secs_f32() * 1000.0;
+
+        let passes = timestamps.map(|t| t.read(&self.device));
+        FrameTime {
+            total,
+            raymarcher: passes.map(|p| p[0]),
+            fullscreen: passes.map(|p| p[1]),
+        }
+    }
+
+    /// Renders the six faces of a cubemap around the camera in +X, -X, +Y, -Y, +Z, -Z order.
+    ///
+    /// Faces are rendered at the current size which should be square.
+    pub fn render_cubemap(&mut self, camera: &Camera, samples: u32) -> [image::RgbaImage; 6] {
+        cubemap_faces().map(|(forward, up)| {
+            let face = Camera {
+                rot: camera.rot * na::UnitQuaternion::face_towards(&forward, &up),
+                // fov is twice the tangent of the half angle so 2 gives the 90 degrees a face needs
+                fov: 2.0,
+                projection: Projection::Perspective,
+                ..*camera
+            };
+            self.render(&face, samples)
+        })
+    }
+}
+
+/// Draws the accumulated image into `output` and copies it back to the cpu.
+///
+/// `output` has to be an 8 bit rgba texture.
+pub(crate) fn read_output(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    fullscreen_renderer: &FullscreenRenderer,
+    output: &wgpu::Texture,
+) -> image::RgbaImage {
+    let (width, height) = (output.width(), output.height());
+    let output_view = output.create_view(&wgpu::TextureViewDescriptor::default());
+
+    // rows of a buffer copy have to be aligned
+    let unpadded_bytes_per_row = width * 4;
+    let padded_bytes_per_row = unpadded_bytes_per_row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Output Buffer"),
+        size: (padded_bytes_per_row * height) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+        label: Some("Output Encoder"),
+    });
+    let pipeline = fullscreen_renderer.pipeline_for(device, output.format());
+    fullscreen_renderer.render_with(
+        pipeline.as_ref().unwrap_or(&fullscreen_renderer.pipeline),
+        &mut encoder,
+        &output_view,
+        None,
+    );
+    encoder.copy_texture_to_buffer(
+        output.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        output.size(),
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device.poll(wgpu::Maintain::Wait);
+
+    let data = slice.get_mapped_range();
+    let pixels: Vec<u8> = data
+        .chunks(padded_bytes_per_row as usize)
+        .flat_map(|row| &row[..unpadded_bytes_per_row as usize])
+        .copied()
+        .collect();
+    drop(data);
+    buffer.unmap();
+
+    image::RgbaImage::from_raw(width, height, pixels).unwrap()
+}
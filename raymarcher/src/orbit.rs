@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{na, Camera};
+
+// How quickly the camera catches up with the orbit, also smooths switching into orbit mode.
+const SMOOTHING: f32 = 10.0;
+const MIN_DISTANCE: f32 = 0.001;
+const MAX_PITCH: f32 = std::f32::consts::FRAC_PI_2 - 0.01;
+
+/// Camera mode that rotates around a target point.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Orbit {
+    pub target: na::Point3<f32>,
+    pub distance: f32,
+    pub yaw: f32,
+    pub pitch: f32,
+}
+impl Orbit {
+    /// Orbits around the point `distance` in front of the camera.
+    pub fn from_camera(camera: &Camera, distance: f32) -> Self {
+        let forward = camera.forward();
+        Self {
+            target: camera.pos + forward * distance,
+            distance: distance.max(MIN_DISTANCE),
+            yaw: forward.x.atan2(forward.z),
+            pitch: (-forward.y).asin().clamp(-MAX_PITCH, MAX_PITCH),
+        }
+    }
+
+    pub fn rotation(&self) -> na::UnitQuaternion<f32> {
+        na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), self.yaw)
+            * na::UnitQuaternion::from_axis_angle(&na::Vector3::x_axis(), self.pitch)
+    }
+    pub fn position(&self) -> na::Point3<f32> {
+        self.target - self.rotation() * na::Vector3::z() * self.distance
+    }
+
+    pub fn rotate(&mut self, yaw: f32, pitch: f32) {
+        self.yaw += yaw;
+        self.pitch = (self.pitch + pitch).clamp(-MAX_PITCH, MAX_PITCH);
+    }
+    /// Positive amounts move towards the target. Zooming is relative so it never goes through it.
+    pub fn zoom(&mut self, amount: f32) {
+        self.distance = (self.distance * (-amount).exp()).max(MIN_DISTANCE);
+    }
+    /// Moves the target in the view plane, scaled by the distance so it feels the same when zoomed in.
+    pub fn pan(&mut self, right: f32, up: f32) {
+        let rot = self.rotation();
+        self.target +=
+            (rot * na::Vector3::x() * right + rot * na::Vector3::y() * up) * self.distance;
+    }
+
+    /// Smoothly moves the camera into place.
+    pub fn follow(&self, camera: &mut Camera, dt: f32) {
+        let t = 1.0 - (-SMOOTHING * dt).exp();
+        camera.pos = camera.pos.coords.lerp(&self.position().coords, t).into();
+        camera.rot = camera.rot.slerp(&self.rotation(), t);
+    }
+}
@@ -0,0 +1,291 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{na, Camera};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Keyframe {
+    /// Seconds from the start of the path.
+    pub time: f32,
+    pub pos: na::Point3<f32>,
+    pub rot: na::UnitQuaternion<f32>,
+    pub fov: f32,
+    pub scene_time: f32,
+}
+impl Keyframe {
+    pub fn from_camera(time: f32, camera: &Camera, scene_time: f32) -> Self {
+        Self {
+            time,
+            pos: camera.pos,
+            rot: camera.rot,
+            fov: camera.fov,
+            scene_time,
+        }
+    }
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.pos = self.pos;
+        camera.rot = self.rot;
+        camera.fov = self.fov;
+    }
+}
+
+/// Camera flythrough made of keyframes sorted by time.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CameraPath {
+    keyframes: Vec<Keyframe>,
+}
+impl CameraPath {
+    pub fn keyframes(&self) -> &[Keyframe] {
+        &self.keyframes
+    }
+    pub fn insert(&mut self, keyframe: Keyframe) {
+        let i = self.keyframes.partition_point(|k| k.time <= keyframe.time);
+        self.keyframes.insert(i, keyframe);
+    }
+    /// Adds a keyframe from the camera `interval` seconds after the last one.
+    pub fn record(&mut self, camera: &Camera, scene_time: f32, interval: f32) {
+        let time = self.keyframes.last().map_or(0.0, |k| k.time + interval);
+        self.insert(Keyframe::from_camera(time, camera, scene_time));
+    }
+    pub fn clear(&mut self) {
+        self.keyframes.clear();
+    }
+    pub fn duration(&self) -> f32 {
+        match (self.keyframes.first(), self.keyframes.last()) {
+            (Some(first), Some(last)) => last.time - first.time,
+            _ => 0.0,
+        }
+    }
+
+    /// Interpolates the path at `time` seconds, clamped to the ends of the path.
+    pub fn sample(&self, time: f32) -> Option<Keyframe> {
+        let keys = &self.keyframes;
+        let last = keys.len().checked_sub(1)?;
+
+        // segment from keys[i] to keys[i + 1]
+        let i = keys
+            .partition_point(|k| k.time <= time)
+            .saturating_sub(1)
+            .min(last.saturating_sub(1));
+        let (a, b) = (&keys[i], &keys[(i + 1).min(last)]);
+        let h = b.time - a.time;
+        if h <= 0.0 {
+            return Some(Keyframe { time, ..*a });
+        }
+        let t = ((time - a.time) / h).clamp(0.0, 1.0);
+
+        // neighbours for the tangents, repeated at the ends
+        let prev = &keys[i.saturating_sub(1)];
+        let next = &keys[(i + 2).min(last)];
+
+        let pos = hermite(
+            a.pos.coords,
+            b.pos.coords,
+            tangent(prev, a, b, |k| k.pos.coords),
+            tangent(a, b, next, |k| k.pos.coords),
+            h,
+            t,
+        );
+        let fov = hermite(
+            a.fov,
+            b.fov,
+            tangent(prev, a, b, |k| k.fov),
+            tangent(a, b, next, |k| k.fov),
+            h,
+            t,
+        );
+        let scene_time = hermite(
+            a.scene_time,
+            b.scene_time,
+            tangent(prev, a, b, |k| k.scene_time),
+            tangent(a, b, next, |k| k.scene_time),
+            h,
+            t,
+        );
+        let rot = squad(prev.rot, a.rot, b.rot, next.rot, t);
+
+        Some(Keyframe {
+            time,
+            pos: pos.into(),
+            rot,
+            fov,
+            scene_time,
+        })
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::read_to_string(path)?;
+        Ok(ron::from_str(&file)?)
+    }
+    pub fn save(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let file = ron::ser::to_string_pretty(self, Default::default())?;
+        std::fs::write(path, file)?;
+        Ok(())
+    }
+}
+
+trait Lerp:
+    Copy
+    + std::ops::Add<Output = Self>
+    + std::ops::Sub<Output = Self>
+    + std::ops::Mul<f32, Output = Self>
+    + std::ops::Div<f32, Output = Self>
+{
+}
+impl<T> Lerp for T where
+    T: Copy
+        + std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<f32, Output = T>
+        + std::ops::Div<f32, Output = T>
+{
+}
+
+/// Catmull-Rom tangent at `b` that takes the uneven spacing of keyframes into account.
+fn tangent<T: Lerp>(a: &Keyframe, b: &Keyframe, c: &Keyframe, value: impl Fn(&Keyframe) -> T) -> T {
+    let dt = c.time - a.time;
+    if dt <= 0.0 {
+        return value(b) * 0.0;
+    }
+    (value(c) - value(a)) / dt
+}
+
+/// Cubic hermite spline between `a` and `b` with tangents per second over a segment `h` seconds long.
+fn hermite<T: Lerp>(a: T, b: T, ma: T, mb: T, h: f32, t: f32) -> T {
+    let t2 = t * t;
+    let t3 = t2 * t;
+    a * (2.0 * t3 - 3.0 * t2 + 1.0)
+        + ma * ((t3 - 2.0 * t2 + t) * h)
+        + b * (-2.0 * t3 + 3.0 * t2)
+        + mb * ((t3 - t2) * h)
+}
+
+fn slerp(
+    a: na::UnitQuaternion<f32>,
+    b: na::UnitQuaternion<f32>,
+    t: f32,
+) -> na::UnitQuaternion<f32> {
+    // only fails when the rotations are half a turn apart
+    a.try_slerp(&b, t, f32::EPSILON)
+        .unwrap_or(if t < 0.5 { a } else { b })
+}
+
+/// Control point for squad that makes the rotation smooth through `q`.
+fn squad_control(
+    prev: na::UnitQuaternion<f32>,
+    q: na::UnitQuaternion<f32>,
+    next: na::UnitQuaternion<f32>,
+) -> na::UnitQuaternion<f32> {
+    // q * exp(-(log(q^-1 next) + log(q^-1 prev)) / 4) written with scaled axes, which are twice the log
+    let inv = q.inverse();
+    let to_next = (inv * next).scaled_axis();
+    let to_prev = (inv * prev).scaled_axis();
+    q * na::UnitQuaternion::from_scaled_axis(-(to_next + to_prev) / 4.0)
+}
+
+fn squad(
+    prev: na::UnitQuaternion<f32>,
+    a: na::UnitQuaternion<f32>,
+    b: na::UnitQuaternion<f32>,
+    next: na::UnitQuaternion<f32>,
+    t: f32,
+) -> na::UnitQuaternion<f32> {
+    // keep neighbours on the same hemisphere so the spline takes the short way around
+    let hemisphere = |from: na::UnitQuaternion<f32>, q: na::UnitQuaternion<f32>| {
+        if from.coords.dot(&q.coords) < 0.0 {
+            na::UnitQuaternion::new_unchecked(-q.into_inner())
+        } else {
+            q
+        }
+    };
+    let prev = hemisphere(a, prev);
+    let b = hemisphere(a, b);
+    let next = hemisphere(b, next);
+
+    let sa = squad_control(prev, a, b);
+    let sb = squad_control(a, b, next);
+    slerp(slerp(a, b, t), slerp(sa, sb, t), 2.0 * t * (1.0 - t))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keyframe(time: f32, x: f32, angle: f32) -> Keyframe {
+        Keyframe {
+            time,
+            pos: na::point![x, x * x, 0.0],
+            rot: na::UnitQuaternion::from_axis_angle(&na::Vector3::y_axis(), angle),
+            fov: 1.0 + x,
+            scene_time: time * 2.0,
+        }
+    }
+
+    fn path(keyframes: impl IntoIterator<Item = Keyframe>) -> CameraPath {
+        let mut path = CameraPath::default();
+        for keyframe in keyframes {
+            path.insert(keyframe);
+        }
+        path
+    }
+
+    #[test]
+    fn samples_hit_the_keyframes() {
+        let path = path([
+            keyframe(0.0, 0.0, 0.0),
+            keyframe(1.0, 1.0, 1.0),
+            keyframe(1.5, -2.0, 2.5),
+            keyframe(4.0, 3.0, -1.0),
+        ]);
+        for key in path.keyframes() {
+            let sample = path.sample(key.time).unwrap();
+            assert!((sample.pos - key.pos).magnitude() < 1e-5, "{sample:?}");
+            assert!(sample.rot.angle_to(&key.rot) < 1e-3, "{sample:?}");
+            assert!((sample.fov - key.fov).abs() < 1e-5, "{sample:?}");
+            assert!(
+                (sample.scene_time - key.scene_time).abs() < 1e-5,
+                "{sample:?}"
+            );
+        }
+        // clamped past the ends
+        assert_eq!(path.sample(-1.0).unwrap().pos, path.keyframes()[0].pos);
+        assert!((path.sample(5.0).unwrap().pos - path.keyframes()[3].pos).magnitude() < 1e-5);
+    }
+
+    #[test]
+    fn rotations_take_the_short_way() {
+        let a = keyframe(0.0, 0.0, 0.0);
+        let mut b = keyframe(1.0, 1.0, std::f32::consts::FRAC_PI_2);
+        // the same rotation on the other side of the quaternion sphere
+        b.rot = na::UnitQuaternion::new_unchecked(-b.rot.into_inner());
+        let path = path([a, b]);
+        for i in 0..=10 {
+            let rot = path.sample(i as f32 / 10.0).unwrap().rot;
+            let (from_a, to_b) = (a.rot.angle_to(&rot), rot.angle_to(&b.rot));
+            assert!(
+                (from_a + to_b - std::f32::consts::FRAC_PI_2).abs() < 1e-3,
+                "{from_a} + {to_b} is the long way"
+            );
+        }
+        let halfway = path.sample(0.5).unwrap().rot;
+        assert!((a.rot.angle_to(&halfway) - std::f32::consts::FRAC_PI_4).abs() < 1e-3);
+    }
+
+    #[test]
+    fn empty_and_single_keyframe_paths() {
+        let empty = CameraPath::default();
+        assert!(empty.keyframes().is_empty());
+        assert_eq!(empty.duration(), 0.0);
+        assert_eq!(empty.sample(0.0), None);
+
+        let key = keyframe(2.0, 1.0, 1.0);
+        let single = path([key]);
+        assert_eq!(single.keyframes(), &[key]);
+        assert_eq!(single.duration(), 0.0);
+        for time in [0.0, 2.0, 5.0] {
+            assert_eq!(single.sample(time), Some(Keyframe { time, ..key }));
+        }
+    }
+}
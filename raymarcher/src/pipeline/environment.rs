@@ -0,0 +1,365 @@
+use wgpu::util::DeviceExt;
+
+use super::{f16_bits, new_fullscreen_quad, Vertex};
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+// bigger images are scaled down to this width for the background
+const MAX_WIDTH: u32 = 4096;
+const IRRADIANCE_SIZE: (u32, u32) = (64, 32);
+const IRRADIANCE_SAMPLES: u32 = 512;
+const SPECULAR_SIZE: (u32, u32) = (256, 128);
+// roughness goes from 0 to 1 over the mips, same as in raymarcher.wgsl
+const SPECULAR_MIPS: u32 = 6;
+const SPECULAR_SAMPLES: u32 = 256;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct PrefilterUniform {
+    source_size: [f32; 2],
+    target_size: [f32; 2],
+    roughness: f32,
+    samples: u32,
+    _padding: [u32; 2],
+}
+
+fn texture(device: &wgpu::Device, label: &str, size: (u32, u32), mips: u32) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size: wgpu::Extent3d {
+            width: size.0,
+            height: size.1,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: mips,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: FORMAT,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    })
+}
+fn mip_view(texture: &wgpu::Texture, mip: u32) -> wgpu::TextureView {
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        base_mip_level: mip,
+        mip_level_count: Some(1),
+        ..Default::default()
+    })
+}
+
+pub fn environment_bindgroup_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let texture = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::FRAGMENT,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: true },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        // background, irradiance and specular
+        entries: &[
+            texture(0),
+            texture(1),
+            texture(2),
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+        label: Some("Environment Bind Group Layout"),
+    })
+}
+
+fn environment_sampler(device: &wgpu::Device) -> wgpu::Sampler {
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Environment Sampler"),
+        // wraps around horizontally
+        address_mode_u: wgpu::AddressMode::Repeat,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        mipmap_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    })
+}
+
+// one of the entry points in environment.wgsl drawn into a mip
+struct Prefilter<'a> {
+    device: &'a wgpu::Device,
+    layout: wgpu::BindGroupLayout,
+    shader: wgpu::ShaderModule,
+    sampler: wgpu::Sampler,
+    mesh: super::Mesh<Vertex>,
+}
+impl<'a> Prefilter<'a> {
+    fn new(device: &'a wgpu::Device) -> Self {
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("Prefilter Bind Group Layout"),
+        });
+        Self {
+            device,
+            layout,
+            shader: device.create_shader_module(wgpu::include_wgsl!("environment.wgsl")),
+            sampler: environment_sampler(device),
+            mesh: new_fullscreen_quad(device),
+        }
+    }
+
+    fn pipeline(&self, entry_point: &str) -> wgpu::RenderPipeline {
+        let layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Prefilter Pipeline Layout"),
+                bind_group_layouts: &[&self.layout],
+                push_constant_ranges: &[],
+            });
+        self.device
+            .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                label: Some(entry_point),
+                layout: Some(&layout),
+                vertex: wgpu::VertexState {
+                    module: &self.shader,
+                    entry_point: "vs_main",
+                    buffers: &[Vertex::desc()],
+                },
+                fragment: Some(wgpu::FragmentState {
+                    module: &self.shader,
+                    entry_point,
+                    targets: &[Some(wgpu::ColorTargetState {
+                        format: FORMAT,
+                        blend: None,
+                        write_mask: wgpu::ColorWrites::ALL,
+                    })],
+                }),
+                primitive: wgpu::PrimitiveState {
+                    front_face: wgpu::FrontFace::Cw,
+                    cull_mode: Some(wgpu::Face::Back),
+                    ..Default::default()
+                },
+                depth_stencil: None,
+                multisample: wgpu::MultisampleState::default(),
+                multiview: None,
+            })
+    }
+
+    fn draw(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        pipeline: &wgpu::RenderPipeline,
+        source: &wgpu::TextureView,
+        target: &wgpu::TextureView,
+        uniform: PrefilterUniform,
+    ) {
+        let buffer = self
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Prefilter Buffer"),
+                contents: bytemuck::cast_slice(&[uniform]),
+                usage: wgpu::BufferUsages::UNIFORM,
+            });
+        let bindgroup = self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: buffer.as_entire_binding(),
+                },
+            ],
+            label: Some("Prefilter Bind Group"),
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Prefilter Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: wgpu::StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        render_pass.set_pipeline(pipeline);
+        render_pass.set_bind_group(0, &bindgroup, &[]);
+        self.mesh.draw(&mut render_pass);
+    }
+}
+
+/// An equirectangular environment along with its blurred versions for lighting.
+pub struct Environment {
+    pub bindgroup: wgpu::BindGroup,
+}
+impl Environment {
+    /// Black, for when there's no environment so the bind group is still there.
+    pub fn empty(device: &wgpu::Device, layout: &wgpu::BindGroupLayout) -> Self {
+        let black = texture(device, "Empty Environment", (1, 1), 1);
+        Self::with_textures(device, layout, &black, &black, &black)
+    }
+
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        layout: &wgpu::BindGroupLayout,
+        image: &image::Rgb32FImage,
+    ) -> Self {
+        let image = if image.width() > MAX_WIDTH {
+            let height = (image.height() * MAX_WIDTH / image.width()).max(1);
+            image::imageops::resize(image, MAX_WIDTH, height, image::imageops::Triangle)
+        } else {
+            image.clone()
+        };
+        let size = image.dimensions();
+        let mips = 32 - size.0.max(size.1).leading_zeros();
+
+        let radiance = texture(device, "Environment Radiance", size, mips);
+        let pixels: Vec<u16> = image
+            .pixels()
+            .flat_map(|p| [p[0], p[1], p[2], 1.0])
+            .map(f16_bits)
+            .collect();
+        queue.write_texture(
+            radiance.as_image_copy(),
+            bytemuck::cast_slice(&pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(size.0 * 8),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: size.0,
+                height: size.1,
+                depth_or_array_layers: 1,
+            },
+        );
+        let irradiance = texture(device, "Environment Irradiance", IRRADIANCE_SIZE, 1);
+        let specular = texture(device, "Environment Specular", SPECULAR_SIZE, SPECULAR_MIPS);
+
+        let prefilter = Prefilter::new(device);
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Prefilter Encoder"),
+        });
+        let uniform = |target: (u32, u32), roughness: f32, samples: u32| PrefilterUniform {
+            source_size: [size.0 as f32, size.1 as f32],
+            target_size: [target.0 as f32, target.1 as f32],
+            roughness,
+            samples,
+            _padding: [0; 2],
+        };
+
+        // mips so the blurring can sample a few texels instead of thousands
+        let downsample = prefilter.pipeline("fs_downsample");
+        for mip in 1..mips {
+            let target = ((size.0 >> mip).max(1), (size.1 >> mip).max(1));
+            prefilter.draw(
+                &mut encoder,
+                &downsample,
+                &mip_view(&radiance, mip - 1),
+                &mip_view(&radiance, mip),
+                uniform(target, 0.0, 0),
+            );
+        }
+
+        let radiance_view = radiance.create_view(&wgpu::TextureViewDescriptor::default());
+        prefilter.draw(
+            &mut encoder,
+            &prefilter.pipeline("fs_irradiance"),
+            &radiance_view,
+            &mip_view(&irradiance, 0),
+            uniform(IRRADIANCE_SIZE, 1.0, IRRADIANCE_SAMPLES),
+        );
+        let specular_pipeline = prefilter.pipeline("fs_specular");
+        for mip in 0..SPECULAR_MIPS {
+            let target = (SPECULAR_SIZE.0 >> mip, SPECULAR_SIZE.1 >> mip);
+            let roughness = mip as f32 / (SPECULAR_MIPS - 1) as f32;
+            prefilter.draw(
+                &mut encoder,
+                &specular_pipeline,
+                &radiance_view,
+                &mip_view(&specular, mip),
+                uniform(target, roughness, SPECULAR_SAMPLES),
+            );
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+
+        Self::with_textures(device, layout, &radiance, &irradiance, &specular)
+    }
+
+    fn with_textures(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        radiance: &wgpu::Texture,
+        irradiance: &wgpu::Texture,
+        specular: &wgpu::Texture,
+    ) -> Self {
+        let view =
+            |texture: &wgpu::Texture| texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bindgroup = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&view(radiance)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&view(irradiance)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&view(specular)),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&environment_sampler(device)),
+                },
+            ],
+            label: Some("Environment Bind Group"),
+        });
+        Self { bindgroup }
+    }
+}
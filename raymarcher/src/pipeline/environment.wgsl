@@ -0,0 +1,128 @@
+// Prefilters an equirectangular environment for image based lighting.
+
+@group(0) @binding(0)
+var source: texture_2d<f32>;
+@group(0) @binding(1)
+var source_sampler: sampler;
+@group(0) @binding(2)
+var<uniform> prefilter: Prefilter;
+
+const PI: f32 = 3.14159265358979323846264338327950288;
+
+struct Prefilter {
+  // of the top mip of the source
+  source_size: vec2<f32>,
+  target_size: vec2<f32>,
+  roughness: f32,
+  samples: u32,
+}
+
+struct VertexIn {
+    @location(0) position: vec2<f32>
+}
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) uv: vec2<f32>
+};
+
+@vertex
+fn vs_main(
+    in: VertexIn
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.clip_position = vec4<f32>(in.position, 0.0, 1.0);
+    let pos = vec2<f32>(in.position.x, -in.position.y);
+    out.uv = (pos + 1.0) / 2.0;
+    return out;
+}
+
+// same as in raymarcher.wgsl
+fn equirect_dir(uv: vec2<f32>) -> vec3<f32> {
+    let lon = (uv.x - 0.5) * 2.0 * PI;
+    let lat = (0.5 - uv.y) * PI;
+    return vec3<f32>(cos(lat) * sin(lon), sin(lat), cos(lat) * cos(lon));
+}
+fn equirect_uv(dir: vec3<f32>) -> vec2<f32> {
+    return vec2<f32>(atan2(dir.x, dir.z) / (2.0 * PI) + 0.5, 0.5 - asin(clamp(dir.y, -1.0, 1.0)) / PI);
+}
+
+fn hammersley(i: u32, n: u32) -> vec2<f32> {
+    return vec2<f32>(f32(i) / f32(n), f32(reverseBits(i)) / 4294967296.0);
+}
+
+// turns `v` from around z to around `n`
+fn around(n: vec3<f32>, v: vec3<f32>) -> vec3<f32> {
+    var up = vec3<f32>(0.0, 1.0, 0.0);
+    if abs(n.y) > 0.999 {
+        up = vec3<f32>(1.0, 0.0, 0.0);
+    }
+    let tangent = normalize(cross(up, n));
+    let bitangent = cross(n, tangent);
+    return tangent * v.x + bitangent * v.y + n * v.z;
+}
+
+// mip to sample so each sample covers its share of the sphere, Colbert and Krivanek 2007
+fn sample_lod(pdf: f32) -> f32 {
+    let texel = 4.0 * PI / (prefilter.source_size.x * prefilter.source_size.y);
+    let sample = 1.0 / (f32(prefilter.samples) * pdf);
+    // never sharper than the target so small ones don't alias
+    let min_lod = log2(prefilter.source_size.x / prefilter.target_size.x);
+    return max(0.5 * log2(sample / texel) + 1.0, min_lod);
+}
+
+// halves the previous mip, linear filtering averages each 2x2 block
+@fragment
+fn fs_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSampleLevel(source, source_sampler, in.uv, 0.0);
+}
+
+// cosine weighted average of the light around each normal, lambert without dividing by pi
+@fragment
+fn fs_irradiance(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = equirect_dir(in.uv);
+
+    var sum = vec3<f32>(0.0);
+    for (var i = 0u; i < prefilter.samples; i++) {
+        let xi = hammersley(i, prefilter.samples);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt(1.0 - xi.y);
+        let sin_theta = sqrt(xi.y);
+        let l = around(n, vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta));
+
+        let pdf = max(cos_theta / PI, 1e-4);
+        sum += textureSampleLevel(source, source_sampler, equirect_uv(l), sample_lod(pdf)).rgb;
+    }
+    return vec4<f32>(sum / f32(prefilter.samples), 1.0);
+}
+
+// the environment blurred by a ggx lobe, assuming the view is along the normal, Karis 2013
+@fragment
+fn fs_specular(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = equirect_dir(in.uv);
+    // a perfect mirror has a pdf of 0 everywhere but one point
+    let a = max(prefilter.roughness * prefilter.roughness, 1e-3);
+    let a2 = a * a;
+
+    var sum = vec3<f32>(0.0);
+    var weight = 0.0;
+    for (var i = 0u; i < prefilter.samples; i++) {
+        let xi = hammersley(i, prefilter.samples);
+        let phi = 2.0 * PI * xi.x;
+        let cos_theta = sqrt((1.0 - xi.y) / (1.0 + (a2 - 1.0) * xi.y));
+        let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+        let h = around(n, vec3<f32>(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta));
+        let l = 2.0 * dot(n, h) * h - n;
+
+        let ndotl = dot(n, l);
+        if ndotl > 0.0 {
+            let den = cos_theta * cos_theta * (a2 - 1.0) + 1.0;
+            let d = a2 / (PI * den * den);
+            // with the view along the normal the pdf is d * ndoth / (4 * vdoth) = d / 4
+            let pdf = max(d / 4.0, 1e-4);
+            sum += textureSampleLevel(source, source_sampler, equirect_uv(l), sample_lod(pdf)).rgb * ndotl;
+            weight += ndotl;
+        }
+    }
+    return vec4<f32>(sum / max(weight, 1e-4), 1.0);
+}
@@ -2,6 +2,33 @@ use std::marker::PhantomData;
 
 use wgpu::util::DeviceExt;
 
+use crate::{
+    distance_cache::DistanceCache,
+    domain::{DomainOp, MAX_DOMAIN_OPS},
+    heightmap::Heightmap,
+    SceneData,
+};
+
+mod environment;
+pub use environment::{environment_bindgroup_layout, Environment};
+
+// the layout tests check the uniforms against this
+const RAYMARCHER_SHADER: &str =
+    concat!(include_str!("noise.wgsl"), include_str!("raymarcher.wgsl"));
+
+// there's no f16 type, only used for uploading so denormals are flushed and values clamped
+fn f16_bits(x: f32) -> u16 {
+    let sign = ((x.to_bits() >> 16) & 0x8000) as u16;
+    let x = x.abs().min(65504.0);
+    if x < 6.103_515_6e-5 {
+        return sign;
+    }
+    let bits = x.to_bits();
+    let exponent = ((bits >> 23) as i32 - 127 + 15) as u16;
+    let mantissa = ((bits >> 13) & 0x3ff) as u16;
+    sign | exponent << 10 | mantissa
+}
+
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct Vertex {
@@ -82,9 +109,20 @@ pub fn new_fullscreen_quad(device: &wgpu::Device) -> Mesh<Vertex> {
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(Copy, Clone, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
     pub matrix: [[f32; 4]; 4],
+    pub aperture: f32,
+    pub focus_dist: f32,
+    pub sample: u32,
+
+    pub projection: u32,
+    pub projection_param: f32,
+
+    pub stereo: u32,
+    pub ipd: f32,
+
+    pub _padding: u32,
 }
 
 pub fn camera_bindgroup_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
@@ -161,11 +199,11 @@ pub fn texture_bindgroup(
         entries: &[
             wgpu::BindGroupEntry {
                 binding: 0,
-                resource: wgpu::BindingResource::TextureView(&view),
+                resource: wgpu::BindingResource::TextureView(view),
             },
             wgpu::BindGroupEntry {
                 binding: 1,
-                resource: wgpu::BindingResource::Sampler(&sampler),
+                resource: wgpu::BindingResource::Sampler(sampler),
             },
         ],
         label: Some("Raymarcher Bind Group"),
@@ -173,7 +211,18 @@ pub fn texture_bindgroup(
 }
 
 #[repr(C)]
-#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+#[derive(
+    Copy,
+    Clone,
+    Debug,
+    PartialEq,
+    bytemuck::Pod,
+    bytemuck::Zeroable,
+    serde::Serialize,
+    serde::Deserialize,
+)]
+// missing fields are defaulted so saved settings keep loading as fields are added
+#[serde(default)]
 pub struct SettingsUniform {
     pub max_steps: i32,
     pub epsilon: f32,
@@ -188,19 +237,96 @@ pub struct SettingsUniform {
     pub time: f32,
     pub scene: u32,
 
-    pub _padding: u32,
+    // filled in from the light list when rendering
+    #[serde(skip)]
+    pub light_count: u32,
+
+    /// Colour of the ground below the horizon, lit by the sun and sky.
+    pub ground_albedo: [f32; 3],
+    /// Haziness of the sky, from 2 for a clear day to around 10.
+    pub turbidity: f32,
+
+    /// Fog density at `fog_height`, 0 for no fog.
+    pub fog_density: f32,
+    /// How quickly the fog thins out going up.
+    pub fog_falloff: f32,
+    pub fog_height: f32,
+    /// Samples along each ray for light shafts through the fog, 0 for plain fog.
+    pub fog_samples: u32,
+
+    // whether there's an environment map, filled in when rendering
+    #[serde(skip)]
+    pub environment: u32,
+    pub environment_intensity: f32,
+    /// Turns the environment map around the vertical axis, in radians.
+    pub environment_rotation: f32,
+
+    /// How many times rays bounce off mirrors or pass through glass, 0 for none.
+    pub bounces: u32,
+    /// How much light is absorbed per unit travelled through glass, for each colour.
+    pub absorption: [f32; 3],
+    /// Index of refraction of the glass, 1.5 for window glass.
+    pub ior: f32,
+    /// 0 for plastic, 1 for metal that reflects in its own colour.
+    pub metallic: f32,
+    /// How much of the surface is glass instead of solid.
+    pub transmission: f32,
+    /// Light the surface gives off in its own colour.
+    pub emission: f32,
+
+    /// Glow around the fractal from rays that pass close to it or take many steps, 0 for none.
+    pub glow_intensity: f32,
+    pub glow_color: [f32; 3],
+    /// How quickly the glow fades away from the surface.
+    pub glow_falloff: f32,
+
+    pub domain: [DomainOp; MAX_DOMAIN_OPS],
+
+    /// How much of the sky is covered in clouds, from 0 to 1.
+    pub cloud_cover: f32,
+
+    /// Height of the terrain from its lowest valleys to where the highest peaks could reach.
+    pub terrain_height: f32,
+    /// Width of the terrain's hills, or of the whole heightmap when there is one.
+    pub terrain_scale: f32,
+    /// Layers of finer and finer detail in the hills.
+    pub terrain_octaves: u32,
+    /// How far up the terrain snow settles, from 0 at the bottom to 1 at the top.
+    pub snow_line: f32,
+
+    // whether the distance cache was baked from the scene as it is, filled in when rendering
+    #[serde(skip)]
+    pub distance_cache: u32,
+    #[serde(skip)]
+    pub _padding: [u32; 2],
+
+    /// Where the centre of a loaded mesh goes.
+    pub mesh_position: [f32; 3],
+    /// Size of a loaded mesh, 1 for its longest side to be 2 units long.
+    pub mesh_scale: f32,
 }
 impl SettingsUniform {
     pub fn set_mandelbulb(mut self) -> Self {
         self.scene = 0;
         self.max_steps = 100;
         self.epsilon = 0.002;
+        self.max_dist = 10.0;
         self
     }
     pub fn set_mengersponge(mut self) -> Self {
         self.scene = 1;
         self.max_steps = 500;
         self.epsilon = 0.0001;
+        self.max_dist = 10.0;
+        self
+    }
+    /// Hills below the camera, the highest peaks reach up to y = 0.
+    pub fn set_terrain(mut self) -> Self {
+        self.scene = 2;
+        // steps are scaled down on steep terrain so rays along it take many
+        self.max_steps = 300;
+        self.epsilon = 0.001;
+        self.max_dist = 30.0;
         self
     }
     pub fn set_mandelbulb_mut(&mut self) {
@@ -209,6 +335,18 @@ impl SettingsUniform {
     pub fn set_mengersponge_mut(&mut self) {
         *self = self.set_mengersponge();
     }
+    pub fn set_terrain_mut(&mut self) {
+        *self = self.set_terrain();
+    }
+    /// Replaces the domain operators, there can be up to `MAX_DOMAIN_OPS` of them.
+    pub fn set_domain(&mut self, ops: &[DomainOp]) {
+        assert!(
+            ops.len() <= MAX_DOMAIN_OPS,
+            "at most {MAX_DOMAIN_OPS} domain operators"
+        );
+        self.domain = Default::default();
+        self.domain[..ops.len()].copy_from_slice(ops);
+    }
 }
 impl Default for SettingsUniform {
     fn default() -> Self {
@@ -222,24 +360,90 @@ impl Default for SettingsUniform {
             alpha: 0.1,
             time: 0.0,
             scene: 0,
-            _padding: 0,
+            light_count: 0,
+            ground_albedo: [0.3, 0.3, 0.3],
+            turbidity: 2.5,
+            fog_density: 0.0,
+            fog_falloff: 1.0,
+            fog_height: 0.0,
+            fog_samples: 0,
+            environment: 0,
+            environment_intensity: 1.0,
+            environment_rotation: 0.0,
+            bounces: 0,
+            absorption: [0.0, 0.0, 0.0],
+            ior: 1.5,
+            metallic: 0.0,
+            transmission: 0.0,
+            emission: 0.0,
+            glow_intensity: 0.0,
+            glow_color: [0.2, 0.6, 1.0],
+            glow_falloff: 20.0,
+            domain: Default::default(),
+            cloud_cover: 0.0,
+            terrain_height: 2.0,
+            terrain_scale: 4.0,
+            terrain_octaves: 6,
+            snow_line: 0.6,
+            distance_cache: 0,
+            _padding: [0; 2],
+            mesh_position: [0.0, 0.0, 0.0],
+            mesh_scale: 1.0,
         }
         .set_mandelbulb()
     }
 }
 
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightUniform {
+    pub position: [f32; 3],
+    pub kind: u32,
+    pub direction: [f32; 3],
+    pub range: f32,
+    // already multiplied by the intensity
+    pub color: [f32; 3],
+    pub radius: f32,
+    pub cos_inner: f32,
+    pub cos_outer: f32,
+    pub shadows: u32,
+    pub _padding: u32,
+}
+
+/// Storage for `capacity` lights, there's always room for one since bindings can't be empty.
+pub fn lights_buffer(device: &wgpu::Device, capacity: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Lights Buffer"),
+        size: (capacity.max(1) * std::mem::size_of::<LightUniform>()) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
 pub fn settings_bindgroup_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[wgpu::BindGroupLayoutEntry {
-            binding: 0,
-            visibility: wgpu::ShaderStages::FRAGMENT,
-            ty: wgpu::BindingType::Buffer {
-                ty: wgpu::BufferBindingType::Uniform,
-                has_dynamic_offset: false,
-                min_binding_size: None,
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
             },
-            count: None,
-        }],
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
         label: Some("Settings Bind Group Layout"),
     })
 }
@@ -247,6 +451,7 @@ pub fn settings_bindgroup(
     device: &wgpu::Device,
     settings_bindgroup_layout: &wgpu::BindGroupLayout,
     settings_uniform: SettingsUniform,
+    lights_buffer: &wgpu::Buffer,
 ) -> BindGroup<SettingsUniform> {
     let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
         label: Some("Settings Buffer"),
@@ -255,10 +460,16 @@ pub fn settings_bindgroup(
     });
     let bindgroup = device.create_bind_group(&wgpu::BindGroupDescriptor {
         layout: settings_bindgroup_layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: buffer.as_entire_binding(),
-        }],
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: lights_buffer.as_entire_binding(),
+            },
+        ],
         label: Some("Settings Bind Group"),
     });
     BindGroup {
@@ -268,19 +479,294 @@ pub fn settings_bindgroup(
     }
 }
 
+/// The heights of `heightmap`, or a buffer the shader sees as no heightmap.
+fn heightmap_buffer(device: &wgpu::Device, heightmap: Option<&Heightmap>) -> wgpu::Buffer {
+    // bindings can't be empty, so there's room for one height when there's none
+    let contents = match heightmap {
+        Some(heightmap) => heightmap.to_bytes(),
+        None => vec![0; 16],
+    };
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Heightmap Buffer"),
+        contents: &contents,
+        usage: wgpu::BufferUsages::STORAGE,
+    })
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MeshUniform {
+    // box the distance grid covers, before the mesh is moved and scaled
+    pub min: [f32; 3],
+    pub loaded: u32,
+    pub size: [f32; 3],
+    pub _padding: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct DistanceCacheUniform {
+    pub min: [f32; 3],
+    pub cell: f32,
+    pub bricks: u32,
+    pub _padding: [u32; 3],
+}
+
+// signed distances on a grid, filtered by the sampler between grid points. bindings can't be
+// empty, so pass a single texel when there's nothing
+fn distance_texture(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    label: &str,
+    size: [u32; 3],
+    distances: &[f32],
+) -> wgpu::TextureView {
+    let size = wgpu::Extent3d {
+        width: size[0],
+        height: size[1],
+        depth_or_array_layers: size[2],
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some(label),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D3,
+        // 32 bit floats can't be filtered everywhere
+        format: wgpu::TextureFormat::R16Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let texels: Vec<u16> = distances.iter().copied().map(f16_bits).collect();
+    queue.write_texture(
+        texture.as_image_copy(),
+        bytemuck::cast_slice(&texels),
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(size.width * 2),
+            rows_per_image: Some(size.height),
+        },
+        size,
+    );
+    texture.create_view(&wgpu::TextureViewDescriptor::default())
+}
+
+// data the scenes are made from
+pub fn scene_bindgroup_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D3,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+        label: Some("Scene Bind Group Layout"),
+    })
+}
+pub fn scene_bindgroup(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    scene_bindgroup_layout: &wgpu::BindGroupLayout,
+    data: &SceneData,
+    distance_cache: Option<&DistanceCache>,
+) -> wgpu::BindGroup {
+    let heightmap_buffer = heightmap_buffer(device, data.heightmap.as_ref());
+    let mesh_view = match &data.mesh {
+        Some(mesh) => distance_texture(
+            device,
+            queue,
+            "Mesh Distance Texture",
+            mesh.size(),
+            mesh.distances(),
+        ),
+        None => distance_texture(device, queue, "Mesh Distance Texture", [1, 1, 1], &[0.0]),
+    };
+    let distance_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Distance Sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter: wgpu::FilterMode::Linear,
+        ..Default::default()
+    });
+    let mesh = match &data.mesh {
+        Some(mesh) => {
+            let (min, size) = mesh.bounds();
+            MeshUniform {
+                min: min.into(),
+                loaded: 1,
+                size: size.into(),
+                _padding: 0,
+            }
+        }
+        None => MeshUniform::default(),
+    };
+    let mesh_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Mesh Buffer"),
+        contents: bytemuck::cast_slice(&[mesh]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    // bindings can't be empty, so there's one brick and distance when there's no cache
+    let (brick_map, distances, cache) = match distance_cache {
+        Some(cache) => {
+            let uniform = DistanceCacheUniform {
+                min: cache.bounds().0.into(),
+                cell: cache.cell(),
+                bricks: cache.bricks(),
+                _padding: [0; 3],
+            };
+            (
+                bytemuck::cast_slice(cache.brick_map()).to_vec(),
+                bytemuck::cast_slice(cache.distances()).to_vec(),
+                uniform,
+            )
+        }
+        None => (vec![0; 8], vec![0; 4], DistanceCacheUniform::default()),
+    };
+    let brick_map_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Distance Cache Brick Buffer"),
+        contents: &brick_map,
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let distances_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Distance Cache Distance Buffer"),
+        // storage buffers can't be empty either when no brick is near the surface
+        contents: if distances.is_empty() {
+            &[0; 4]
+        } else {
+            &distances
+        },
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let cache_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some("Distance Cache Buffer"),
+        contents: bytemuck::cast_slice(&[cache]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout: scene_bindgroup_layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: heightmap_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::TextureView(&mesh_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Sampler(&distance_sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: mesh_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: brick_map_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: cache_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: distances_buffer.as_entire_binding(),
+            },
+        ],
+        label: Some("Scene Bind Group"),
+    })
+}
+
 pub fn raymarcher_pipeline(
     device: &wgpu::Device,
     format: wgpu::TextureFormat,
     camera_bindgroup_layout: &wgpu::BindGroupLayout,
     settings_bindgroup_layout: &wgpu::BindGroupLayout,
+    environment_bindgroup_layout: &wgpu::BindGroupLayout,
+    scene_bindgroup_layout: &wgpu::BindGroupLayout,
 ) -> wgpu::RenderPipeline {
     let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
         label: Some("Raymarcher Pipeline Layout"),
-        bind_group_layouts: &[camera_bindgroup_layout, settings_bindgroup_layout],
+        bind_group_layouts: &[
+            camera_bindgroup_layout,
+            settings_bindgroup_layout,
+            environment_bindgroup_layout,
+            scene_bindgroup_layout,
+        ],
         push_constant_ranges: &[],
     });
 
-    let shader = device.create_shader_module(wgpu::include_wgsl!("raymarcher.wgsl"));
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("raymarcher.wgsl"),
+        source: wgpu::ShaderSource::Wgsl(RAYMARCHER_SHADER.into()),
+    });
 
     device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
         label: Some("Raymarcher Pipeline"),
@@ -295,7 +781,19 @@ pub fn raymarcher_pipeline(
             entry_point: "fs_main",
             targets: &[Some(wgpu::ColorTargetState {
                 format,
-                blend: Some(wgpu::BlendState::REPLACE),
+                // the shader outputs 1 / (sample + 1) as alpha so blending keeps a running average
+                blend: Some(wgpu::BlendState {
+                    color: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::SrcAlpha,
+                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                        operation: wgpu::BlendOperation::Add,
+                    },
+                    alpha: wgpu::BlendComponent {
+                        src_factor: wgpu::BlendFactor::One,
+                        dst_factor: wgpu::BlendFactor::One,
+                        operation: wgpu::BlendOperation::Max,
+                    },
+                }),
                 write_mask: wgpu::ColorWrites::ALL,
             })],
         }),
@@ -366,3 +864,174 @@ pub fn fullscreen_pipeline(
         multiview: None,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::distance_cache::Brick;
+
+    // name, offset and size of each field, leaving out padding
+    macro_rules! layout {
+        ($t:ty { $($field:ident),* $(,)? }) => {{
+            let value: $t = bytemuck::Zeroable::zeroed();
+            vec![$((
+                stringify!($field),
+                std::mem::offset_of!($t, $field),
+                std::mem::size_of_val(&value.$field),
+            )),*]
+        }};
+    }
+
+    fn check_layout(name: &str, size: usize, fields: Vec<(&str, usize, usize)>) {
+        let module = naga::front::wgsl::parse_str(RAYMARCHER_SHADER).unwrap();
+        let (members, span) = module
+            .types
+            .iter()
+            .find_map(|(_, ty)| match &ty.inner {
+                naga::TypeInner::Struct { members, span } if ty.name.as_deref() == Some(name) => {
+                    Some((members, *span))
+                }
+                _ => None,
+            })
+            .unwrap_or_else(|| panic!("there is no struct {name} in the shader"));
+
+        let shader_fields: Vec<_> = members
+            .iter()
+            .map(|member| {
+                (
+                    member.name.as_deref().unwrap_or_default(),
+                    member.offset as usize,
+                    module.types[member.ty].inner.size(module.to_ctx()) as usize,
+                )
+            })
+            .collect();
+        assert_eq!(
+            fields, shader_fields,
+            "{name} doesn't line up with the shader"
+        );
+        assert_eq!(
+            size, span as usize,
+            "{name} is a different size in the shader"
+        );
+    }
+
+    #[test]
+    fn camera_layout() {
+        check_layout(
+            "Camera",
+            std::mem::size_of::<CameraUniform>(),
+            layout!(CameraUniform {
+                matrix,
+                aperture,
+                focus_dist,
+                sample,
+                projection,
+                projection_param,
+                stereo,
+                ipd,
+            }),
+        );
+    }
+
+    #[test]
+    fn settings_layout() {
+        check_layout(
+            "Settings",
+            std::mem::size_of::<SettingsUniform>(),
+            layout!(SettingsUniform {
+                max_steps,
+                epsilon,
+                max_dist,
+                sun_size,
+                sun_dir,
+                sun_sharpness,
+                alpha,
+                time,
+                scene,
+                light_count,
+                ground_albedo,
+                turbidity,
+                fog_density,
+                fog_falloff,
+                fog_height,
+                fog_samples,
+                environment,
+                environment_intensity,
+                environment_rotation,
+                bounces,
+                absorption,
+                ior,
+                metallic,
+                transmission,
+                emission,
+                glow_intensity,
+                glow_color,
+                glow_falloff,
+                domain,
+                cloud_cover,
+                terrain_height,
+                terrain_scale,
+                terrain_octaves,
+                snow_line,
+                distance_cache,
+                mesh_position,
+                mesh_scale,
+            }),
+        );
+    }
+
+    #[test]
+    fn domain_layout() {
+        check_layout(
+            "DomainOp",
+            std::mem::size_of::<DomainOp>(),
+            layout!(DomainOp { a, kind, b, param }),
+        );
+    }
+
+    #[test]
+    fn mesh_layout() {
+        check_layout(
+            "Mesh",
+            std::mem::size_of::<MeshUniform>(),
+            layout!(MeshUniform { min, loaded, size }),
+        );
+    }
+
+    #[test]
+    fn distance_cache_layout() {
+        check_layout(
+            "DistanceCache",
+            std::mem::size_of::<DistanceCacheUniform>(),
+            layout!(DistanceCacheUniform { min, cell, bricks }),
+        );
+    }
+
+    #[test]
+    fn cache_brick_layout() {
+        check_layout(
+            "CacheBrick",
+            std::mem::size_of::<Brick>(),
+            layout!(Brick { distance, dense }),
+        );
+    }
+
+    #[test]
+    fn light_layout() {
+        check_layout(
+            "Light",
+            std::mem::size_of::<LightUniform>(),
+            layout!(LightUniform {
+                position,
+                kind,
+                direction,
+                range,
+                color,
+                radius,
+                cos_inner,
+                cos_outer,
+                shadows,
+            }),
+        );
+    }
+}
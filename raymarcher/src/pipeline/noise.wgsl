@@ -0,0 +1,257 @@
+// Noise for displacement, patterns and clouds, put in front of raymarcher.wgsl.
+// noise.rs has the same functions for the CPU, keep them in sync.
+
+const NOISE_VALUE: u32 = 0u;
+const NOISE_PERLIN: u32 = 1u;
+const NOISE_SIMPLEX: u32 = 2u;
+const NOISE_WORLEY: u32 = 3u;
+
+// pcg hash
+fn hash(x: u32) -> u32 {
+    let state = x * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+fn hash2(c: vec2<i32>) -> u32 {
+    return hash(bitcast<u32>(c.x) + hash(bitcast<u32>(c.y)));
+}
+fn hash3(c: vec3<i32>) -> u32 {
+    return hash(bitcast<u32>(c.x) + hash(bitcast<u32>(c.y) + hash(bitcast<u32>(c.z))));
+}
+// between 0 and 1
+fn unit(h: u32) -> f32 {
+    return f32(h) / 4294967295.0;
+}
+
+// value noise between -1 and 1, random values at the lattice points smoothly blended
+
+fn value_noise2(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = p - i;
+    let u = f * f * (3.0 - 2.0 * f);
+    let c = vec2<i32>(i);
+
+    let x0 = mix(unit(hash2(c)), unit(hash2(c + vec2<i32>(1, 0))), u.x);
+    let x1 = mix(unit(hash2(c + vec2<i32>(0, 1))), unit(hash2(c + vec2<i32>(1, 1))), u.x);
+    return mix(x0, x1, u.y) * 2.0 - 1.0;
+}
+fn value_noise3(p: vec3<f32>) -> f32 {
+    let i = floor(p);
+    let f = p - i;
+    let u = f * f * (3.0 - 2.0 * f);
+    let c = vec3<i32>(i);
+
+    let x00 = mix(unit(hash3(c)), unit(hash3(c + vec3<i32>(1, 0, 0))), u.x);
+    let x10 = mix(unit(hash3(c + vec3<i32>(0, 1, 0))), unit(hash3(c + vec3<i32>(1, 1, 0))), u.x);
+    let x01 = mix(unit(hash3(c + vec3<i32>(0, 0, 1))), unit(hash3(c + vec3<i32>(1, 0, 1))), u.x);
+    let x11 = mix(unit(hash3(c + vec3<i32>(0, 1, 1))), unit(hash3(c + vec3<i32>(1, 1, 1))), u.x);
+    return mix(mix(x00, x10, u.y), mix(x01, x11, u.y), u.z) * 2.0 - 1.0;
+}
+// steepest slope of value_noise3, 1.5 along each axis between lattice values 2 apart
+const VALUE_NOISE_SLOPE: f32 = 5.196152;
+
+// perlin's gradient noise, around -1 to 1
+
+// one of 8 directions around a circle
+fn gradient2(h: u32, f: vec2<f32>) -> f32 {
+    let k = h & 7u;
+    let sx = select(-1.0, 1.0, (k & 1u) == 0u);
+    let sy = select(-1.0, 1.0, (k & 2u) == 0u);
+    if k < 4u {
+        return (sx * f.x + sy * f.y) * 0.70710678;
+    }
+    return select(sx * f.y, sx * f.x, (k & 2u) == 0u);
+}
+// one of the 12 edges of a cube, Perlin 2002
+fn gradient3(h: u32, f: vec3<f32>) -> f32 {
+    let k = h & 15u;
+    let u = select(f.y, f.x, k < 8u);
+    let v = select(select(f.z, f.x, k == 12u || k == 14u), f.y, k < 4u);
+    return select(-u, u, (k & 1u) == 0u) + select(-v, v, (k & 2u) == 0u);
+}
+fn fade(t: f32) -> f32 {
+    return t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+}
+
+fn perlin_noise2(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = p - i;
+    let c = vec2<i32>(i);
+
+    let n00 = gradient2(hash2(c), f);
+    let n10 = gradient2(hash2(c + vec2<i32>(1, 0)), f - vec2<f32>(1.0, 0.0));
+    let n01 = gradient2(hash2(c + vec2<i32>(0, 1)), f - vec2<f32>(0.0, 1.0));
+    let n11 = gradient2(hash2(c + vec2<i32>(1, 1)), f - vec2<f32>(1.0, 1.0));
+    let u = fade(f.x);
+    return mix(mix(n00, n10, u), mix(n01, n11, u), fade(f.y)) * 1.41421356;
+}
+// a little over the steepest slope of perlin_noise2 found by sampling it
+const PERLIN_NOISE_SLOPE: f32 = 3.0;
+fn perlin_noise3(p: vec3<f32>) -> f32 {
+    let i = floor(p);
+    let f = p - i;
+    let c = vec3<i32>(i);
+
+    let n000 = gradient3(hash3(c), f);
+    let n100 = gradient3(hash3(c + vec3<i32>(1, 0, 0)), f - vec3<f32>(1.0, 0.0, 0.0));
+    let n010 = gradient3(hash3(c + vec3<i32>(0, 1, 0)), f - vec3<f32>(0.0, 1.0, 0.0));
+    let n110 = gradient3(hash3(c + vec3<i32>(1, 1, 0)), f - vec3<f32>(1.0, 1.0, 0.0));
+    let n001 = gradient3(hash3(c + vec3<i32>(0, 0, 1)), f - vec3<f32>(0.0, 0.0, 1.0));
+    let n101 = gradient3(hash3(c + vec3<i32>(1, 0, 1)), f - vec3<f32>(1.0, 0.0, 1.0));
+    let n011 = gradient3(hash3(c + vec3<i32>(0, 1, 1)), f - vec3<f32>(0.0, 1.0, 1.0));
+    let n111 = gradient3(hash3(c + vec3<i32>(1, 1, 1)), f - vec3<f32>(1.0, 1.0, 1.0));
+    let u = fade(f.x);
+    let v = fade(f.y);
+    let y0 = mix(mix(n000, n100, u), mix(n010, n110, u), v);
+    let y1 = mix(mix(n001, n101, u), mix(n011, n111, u), v);
+    return mix(y0, y1, fade(f.z));
+}
+
+// simplex noise, around -1 to 1, gradient noise on a triangular grid without the
+// square grid's artifacts, Gustavson 2005
+
+fn simplex_corner2(c: vec2<i32>, x: vec2<f32>) -> f32 {
+    let t = max(0.5 - dot(x, x), 0.0);
+    return t * t * t * t * gradient2(hash2(c), x);
+}
+fn simplex_noise2(p: vec2<f32>) -> f32 {
+    let f2 = 0.36602540; // (sqrt(3) - 1) / 2
+    let g2 = 0.21132487; // (3 - sqrt(3)) / 6
+
+    let i = floor(p + (p.x + p.y) * f2);
+    let x0 = p - (i - (i.x + i.y) * g2);
+    let o = select(vec2<f32>(0.0, 1.0), vec2<f32>(1.0, 0.0), x0.x > x0.y);
+    let x1 = x0 - o + g2;
+    let x2 = x0 - 1.0 + 2.0 * g2;
+
+    let c = vec2<i32>(i);
+    let n = simplex_corner2(c, x0)
+        + simplex_corner2(c + vec2<i32>(o), x1)
+        + simplex_corner2(c + vec2<i32>(1, 1), x2);
+    return n * 99.2;
+}
+fn simplex_corner3(c: vec3<i32>, x: vec3<f32>) -> f32 {
+    let t = max(0.6 - dot(x, x), 0.0);
+    return t * t * t * t * gradient3(hash3(c), x);
+}
+fn simplex_noise3(p: vec3<f32>) -> f32 {
+    let f3 = 1.0 / 3.0;
+    let g3 = 1.0 / 6.0;
+
+    let i = floor(p + (p.x + p.y + p.z) * f3);
+    let x0 = p - (i - (i.x + i.y + i.z) * g3);
+    // which of the six tetrahedra in the cube
+    let ge = step(x0.yzx, x0.xyz);
+    let le = 1.0 - ge;
+    let o1 = min(ge, le.zxy);
+    let o2 = max(ge, le.zxy);
+    let x1 = x0 - o1 + g3;
+    let x2 = x0 - o2 + 2.0 * g3;
+    let x3 = x0 - 1.0 + 3.0 * g3;
+
+    let c = vec3<i32>(i);
+    let n = simplex_corner3(c, x0)
+        + simplex_corner3(c + vec3<i32>(o1), x1)
+        + simplex_corner3(c + vec3<i32>(o2), x2)
+        + simplex_corner3(c + vec3<i32>(1, 1, 1), x3);
+    return n * 32.0;
+}
+
+// worley noise, the distance to the nearest of points scattered one per cell, 0 to a little over 1
+
+fn worley_noise2(p: vec2<f32>) -> f32 {
+    let i = floor(p);
+    let f = p - i;
+    let c = vec2<i32>(i);
+
+    var closest = 8.0;
+    for (var y = -1; y <= 1; y++) {
+        for (var x = -1; x <= 1; x++) {
+            let h = hash2(c + vec2<i32>(x, y));
+            let point = vec2<f32>(unit(h), unit(hash(h)));
+            let d = vec2<f32>(f32(x), f32(y)) + point - f;
+            closest = min(closest, dot(d, d));
+        }
+    }
+    return sqrt(closest);
+}
+fn worley_noise3(p: vec3<f32>) -> f32 {
+    let i = floor(p);
+    let f = p - i;
+    let c = vec3<i32>(i);
+
+    var closest = 8.0;
+    for (var z = -1; z <= 1; z++) {
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                let h = hash3(c + vec3<i32>(x, y, z));
+                let point = vec3<f32>(unit(h), unit(hash(h)), unit(hash(hash(h))));
+                let d = vec3<f32>(f32(x), f32(y), f32(z)) + point - f;
+                closest = min(closest, dot(d, d));
+            }
+        }
+    }
+    return sqrt(closest);
+}
+
+fn noise2(kind: u32, p: vec2<f32>) -> f32 {
+    switch kind {
+        case NOISE_PERLIN {
+            return perlin_noise2(p);
+        }
+        case NOISE_SIMPLEX {
+            return simplex_noise2(p);
+        }
+        case NOISE_WORLEY {
+            return worley_noise2(p);
+        }
+        default {
+            return value_noise2(p);
+        }
+    }
+}
+fn noise3(kind: u32, p: vec3<f32>) -> f32 {
+    switch kind {
+        case NOISE_PERLIN {
+            return perlin_noise3(p);
+        }
+        case NOISE_SIMPLEX {
+            return simplex_noise3(p);
+        }
+        case NOISE_WORLEY {
+            return worley_noise3(p);
+        }
+        default {
+            return value_noise3(p);
+        }
+    }
+}
+
+// octaves of noise, each `lacunarity` times finer and `gain` times weaker than the last,
+// scaled back to the range of the noise
+fn fbm2(kind: u32, p: vec2<f32>, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    var sum = 0.0;
+    var total = 0.0;
+    var amplitude = 1.0;
+    var q = p;
+    for (var i = 0u; i < octaves; i++) {
+        sum += amplitude * noise2(kind, q);
+        total += amplitude;
+        amplitude *= gain;
+        q *= lacunarity;
+    }
+    return sum / max(total, 1e-6);
+}
+fn fbm3(kind: u32, p: vec3<f32>, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+    var sum = 0.0;
+    var total = 0.0;
+    var amplitude = 1.0;
+    var q = p;
+    for (var i = 0u; i < octaves; i++) {
+        sum += amplitude * noise3(kind, q);
+        total += amplitude;
+        amplitude *= gain;
+        q *= lacunarity;
+    }
+    return sum / max(total, 1e-6);
+}
@@ -1,10 +1,47 @@
 @group(0) @binding(0)
-var<uniform> camera: mat4x4<f32>;
+var<uniform> camera: Camera;
 @group(1) @binding(0)
 var<uniform> settings: Settings;
+@group(1) @binding(1)
+var<storage, read> lights: array<Light>;
+@group(2) @binding(0)
+var environment_radiance: texture_2d<f32>;
+@group(2) @binding(1)
+var environment_irradiance: texture_2d<f32>;
+@group(2) @binding(2)
+var environment_specular: texture_2d<f32>;
+@group(2) @binding(3)
+var environment_sampler: sampler;
+@group(3) @binding(0)
+var<storage, read> heightmap: Heightmap;
+@group(3) @binding(1)
+var mesh_distances: texture_3d<f32>;
+@group(3) @binding(2)
+var distance_sampler: sampler;
+@group(3) @binding(3)
+var<uniform> mesh: Mesh;
+@group(3) @binding(4)
+var<storage, read> cache_bricks: array<CacheBrick>;
+@group(3) @binding(5)
+var<uniform> distance_cache: DistanceCache;
+@group(3) @binding(6)
+var<storage, read> cache_distances: array<f32>;
 
 const PI: f32 = 3.14159265358979323846264338327950288;
 
+struct Camera {
+  matrix: mat4x4<f32>,
+  aperture: f32,
+  focus_dist: f32,
+  sample: u32,
+
+  projection: u32,
+  projection_param: f32,
+
+  stereo: u32,
+  ipd: f32,
+}
+
 struct Settings {
   max_steps: i32,
   epsilon: f32,
@@ -17,7 +54,109 @@ struct Settings {
   alpha: f32,
 
   time: f32,
-  scene: u32
+  scene: u32,
+  light_count: u32,
+
+  ground_albedo: vec3<f32>,
+  turbidity: f32,
+
+  fog_density: f32,
+  fog_falloff: f32,
+  fog_height: f32,
+  fog_samples: u32,
+
+  environment: u32,
+  environment_intensity: f32,
+  environment_rotation: f32,
+
+  bounces: u32,
+  absorption: vec3<f32>,
+  ior: f32,
+  metallic: f32,
+  transmission: f32,
+  emission: f32,
+
+  glow_intensity: f32,
+  glow_color: vec3<f32>,
+  glow_falloff: f32,
+
+  domain: array<DomainOp, MAX_DOMAIN_OPS>,
+
+  cloud_cover: f32,
+
+  terrain_height: f32,
+  terrain_scale: f32,
+  terrain_octaves: u32,
+  snow_line: f32,
+
+  distance_cache: u32,
+
+  mesh_position: vec3<f32>,
+  mesh_scale: f32,
+}
+
+// same as heightmap.rs, a width of 0 means there's no heightmap
+struct Heightmap {
+  width: u32,
+  height: u32,
+  slope: f32,
+  heights: array<f32>,
+}
+
+// same as MeshUniform in mod.rs
+struct Mesh {
+  min: vec3<f32>,
+  loaded: u32,
+  size: vec3<f32>,
+}
+
+// same as DistanceCacheUniform in mod.rs
+struct DistanceCache {
+  min: vec3<f32>,
+  cell: f32,
+  // along each side
+  bricks: u32,
+}
+
+struct CacheBrick {
+  // safe to step anywhere in the brick when it's far from the surface
+  distance: f32,
+  // 1 + which grid of cache_distances it has when it's near the surface, 0 when it's far
+  dense: u32,
+}
+
+// same as in domain.rs
+const MAX_DOMAIN_OPS: u32 = 4u;
+const DOMAIN_REPEAT: u32 = 1u;
+const DOMAIN_MIRROR: u32 = 2u;
+const DOMAIN_POLAR: u32 = 3u;
+const DOMAIN_TWIST: u32 = 4u;
+const DOMAIN_BEND: u32 = 5u;
+const DOMAIN_DISPLACE: u32 = 6u;
+
+struct DomainOp {
+  a: vec3<f32>,
+  kind: u32,
+  b: vec3<f32>,
+  param: f32,
+}
+
+const LIGHT_DIRECTIONAL: u32 = 0u;
+const LIGHT_POINT: u32 = 1u;
+const LIGHT_SPOT: u32 = 2u;
+const LIGHT_SPHERE: u32 = 3u;
+
+struct Light {
+  position: vec3<f32>,
+  kind: u32,
+  // towards a directional light, where a spot light points
+  direction: vec3<f32>,
+  range: f32,
+  color: vec3<f32>,
+  radius: f32,
+  cos_inner: f32,
+  cos_outer: f32,
+  shadows: u32,
 }
 
 fn distributionGGX(a: f32, n: vec3<f32>, h: vec3<f32>) -> f32 {
@@ -150,9 +289,9 @@ fn mandelbulb(pos: vec3<f32>) -> f32 {
             break;
         }
 		
-		// convert to polar coordinates
-		var theta = acos(z.z/r);
-		var phi = atan2(z.y,z.x);
+		// convert to polar coordinates, at the origin the angles don't matter since z^power is 0
+		var theta = select(0.0, acos(z.z/r), r > 0.0);
+		var phi = select(0.0, atan2(z.y,z.x), r > 0.0);
 		dr = pow(r, power - 1.0)*power*dr + 1.0;
 		
 		// scale and rotate the point
@@ -164,21 +303,180 @@ fn mandelbulb(pos: vec3<f32>) -> f32 {
 		z = zr*vec3<f32>(sin(theta)*cos(phi), sin(phi)*sin(theta), cos(theta));
 		z+=pos;
 	}
-	return 0.5*log(r)*r/dr;
+	// the origin never escapes, it's inside
+	return select(0.0, 0.5*log(r)*r/dr, r > 0.0);
 }
 
-fn de(p: vec3<f32>) -> f32 {
+fn heightmap_texel(x: i32, y: i32) -> f32 {
+    let cx = u32(clamp(x, 0, i32(heightmap.width) - 1));
+    let cy = u32(clamp(y, 0, i32(heightmap.height) - 1));
+    return heightmap.heights[cy * heightmap.width + cx];
+}
+// blended by hand since storage buffers can't be filtered, like sample() in heightmap.rs
+fn sample_heightmap(uv: vec2<f32>) -> f32 {
+    let t = uv * vec2<f32>(f32(heightmap.width), f32(heightmap.height)) - 0.5;
+    let i = floor(t);
+    let f = t - i;
+    let x = i32(i.x);
+    let y = i32(i.y);
+
+    let y0 = mix(heightmap_texel(x, y), heightmap_texel(x + 1, y), f.x);
+    let y1 = mix(heightmap_texel(x, y + 1), heightmap_texel(x + 1, y + 1), f.x);
+    return mix(y0, y1, f.y);
+}
+
+const TERRAIN_LACUNARITY: f32 = 2.0;
+const TERRAIN_GAIN: f32 = 0.5;
+
+// from 0 in the valleys to 1 at the peaks
+fn terrain_level(xz: vec2<f32>) -> f32 {
+    if heightmap.width > 0u {
+        return sample_heightmap(xz / settings.terrain_scale + 0.5);
+    }
+    let n = fbm2(NOISE_PERLIN, xz / settings.terrain_scale, settings.terrain_octaves, TERRAIN_LACUNARITY, TERRAIN_GAIN);
+    return n * 0.5 + 0.5;
+}
+// steepest the ground can get, so steps can be shortened to never cut through a hillside
+fn terrain_slope() -> f32 {
+    if heightmap.width > 0u {
+        let texels = f32(max(heightmap.width, heightmap.height)) / settings.terrain_scale;
+        return settings.terrain_height * heightmap.slope * texels;
+    }
+    // every octave is as steep as the first, being as much finer as it is lower
+    let octaves = f32(settings.terrain_octaves);
+    let total = 2.0 * (1.0 - pow(TERRAIN_GAIN, octaves));
+    let fbm_slope = PERLIN_NOISE_SLOPE * octaves / max(total, 1e-6);
+    return settings.terrain_height * 0.5 * fbm_slope / settings.terrain_scale;
+}
+fn terrain(p: vec3<f32>) -> f32 {
+    let ground = settings.terrain_height * (terrain_level(p.xz) - 1.0);
+    // the height above the ground is only the distance on flat ground, on a slope
+    // the ground can be as close as this. nothing reaches above y = 0 either
+    let slope = terrain_slope();
+    return max((p.y - ground) / sqrt(1.0 + slope * slope), p.y);
+}
+
+// same as MeshSdf::distance in mesh.rs
+fn mesh_distance(p: vec3<f32>) -> f32 {
+    let local = (p - settings.mesh_position) / settings.mesh_scale;
+    let inside = clamp(local, mesh.min, mesh.min + mesh.size);
+    let d = textureSampleLevel(mesh_distances, distance_sampler, (inside - mesh.min) / mesh.size, 0.0).r;
+    // outside the grid the mesh is at least as far as the way into it and on from there
+    let outside = length(local - inside);
+    if outside > 0.0 {
+        return sqrt(outside * outside + max(d, 0.0) * max(d, 0.0)) * settings.mesh_scale;
+    }
+    return d * settings.mesh_scale;
+}
+
+fn scene_distance(p: vec3<f32>) -> f32 {
+    var d: f32;
     switch settings.scene {
         case 0u {
-            return mandelbulb(p);
+            d = mandelbulb(p);
         }
         case 1u {
-            return menger_sponge(p);
+            d = menger_sponge(p);
+        }
+        case 2u {
+            d = terrain(p);
         }
         default {
-            return sphere_distance(p);
+            d = sphere_distance(p);
+        }
+    }
+    if mesh.loaded != 0u {
+        d = min(d, mesh_distance(p));
+    }
+    return d;
+}
+
+fn rotate(v: vec2<f32>, a: f32) -> vec2<f32> {
+    let c = cos(a);
+    let s = sin(a);
+    return vec2<f32>(c * v.x - s * v.y, s * v.x + c * v.y);
+}
+
+// the scene seen through the domain operators. operators that stretch space or make the
+// distance change faster scale it down by as much so the march doesn't overshoot
+fn de(p: vec3<f32>) -> f32 {
+    var q = p;
+    var stretch = 1.0;
+    var displacement = 0.0;
+    var displacement_slope = 0.0;
+    for (var i = 0u; i < MAX_DOMAIN_OPS; i++) {
+        let op = settings.domain[i];
+        switch op.kind {
+            case DOMAIN_REPEAT {
+                let cell = round(q / max(op.a, vec3<f32>(1e-6)));
+                // a negative limit repeats forever
+                let limit = select(op.b, vec3<f32>(1e9), op.b < vec3<f32>(0.0));
+                let repeated = q - op.a * clamp(cell, -limit, limit);
+                q = select(q, repeated, op.a > vec3<f32>(0.0));
+            }
+            case DOMAIN_MIRROR {
+                let d = dot(q, op.a) - op.param;
+                q -= 2.0 * min(d, 0.0) * op.a;
+            }
+            case DOMAIN_POLAR {
+                let sector = 2.0 * PI / max(op.param, 1.0);
+                let a = real_mod_f32(atan2(q.z, q.x) + sector / 2.0, sector) - sector / 2.0;
+                let r = length(q.xz);
+                q = vec3<f32>(r * cos(a), q.y, r * sin(a));
+            }
+            case DOMAIN_TWIST {
+                let xz = rotate(q.xz, op.param * q.y);
+                q = vec3<f32>(xz.x, q.y, xz.y);
+                // largest stretch of a shear by s
+                let s = abs(op.param) * length(q.xz);
+                stretch *= 0.5 * (s + sqrt(s * s + 4.0));
+            }
+            case DOMAIN_BEND {
+                let xy = rotate(q.xy, op.param * q.x);
+                q = vec3<f32>(xy, q.z);
+                // the shear can line up with x here, so it can stretch by all of it
+                stretch *= 1.0 + abs(op.param) * length(q.xy);
+            }
+            case DOMAIN_DISPLACE {
+                displacement += op.param * value_noise3(q * op.a.x);
+                displacement_slope += stretch * abs(op.param * op.a.x) * VALUE_NOISE_SLOPE;
+            }
+            default {}
         }
     }
+    return (scene_distance(q) + displacement) / (stretch + displacement_slope);
+}
+
+// same as in distance_cache.rs
+const DISTANCE_CACHE_BRICK: u32 = 8u;
+// the nearest grid point is up to half a cell's diagonal away
+const DISTANCE_CACHE_MARGIN: f32 = 1.0;
+
+// the distance estimate for stepping along rays, looked up in the distance cache when it's far
+// enough from the surface for the cache to be sure of and worked out exactly when it's close
+fn march_de(p: vec3<f32>) -> f32 {
+    if settings.distance_cache != 0u {
+        let cells = (p - distance_cache.min) / distance_cache.cell;
+        let n = distance_cache.bricks;
+        let size = f32(n * DISTANCE_CACHE_BRICK);
+        if all(cells >= vec3<f32>(0.0)) && all(cells < vec3<f32>(size)) {
+            let c = vec3<u32>(cells);
+            let b = c / DISTANCE_CACHE_BRICK;
+            let brick = cache_bricks[(b.z * n + b.y) * n + b.x];
+            if brick.dense == 0u {
+                return brick.distance;
+            }
+            let l = c % DISTANCE_CACHE_BRICK;
+            let cell = (l.z * DISTANCE_CACHE_BRICK + l.y) * DISTANCE_CACHE_BRICK + l.x;
+            let brick_cells = DISTANCE_CACHE_BRICK * DISTANCE_CACHE_BRICK * DISTANCE_CACHE_BRICK;
+            let d = cache_distances[(brick.dense - 1u) * brick_cells + cell];
+            let margin = distance_cache.cell * DISTANCE_CACHE_MARGIN;
+            if abs(d) > 2.0 * margin {
+                return d - sign(d) * margin;
+            }
+        }
+    }
+    return de(p);
 }
 
 fn calc_normal(p: vec3<f32>, d: f32) -> vec3<f32> {
@@ -186,15 +484,20 @@ fn calc_normal(p: vec3<f32>, d: f32) -> vec3<f32> {
     let dx = de(p + vec3<f32>(d, 0.0, 0.0)) - x;
     let dy = de(p + vec3<f32>(0.0, d, 0.0)) - x;
     let dz = de(p + vec3<f32>(0.0, 0.0, d)) - x;
-    return normalize(vec3<f32>(dx, dy ,dz));
+    let g = vec3<f32>(dx, dy ,dz);
+    // far from the origin the differences can round to nothing, same fallback as sdf.rs
+    if dot(g, g) == 0.0 {
+        return vec3<f32>(0.0, 1.0, 0.0);
+    }
+    return normalize(g);
 }
 
-fn shadow(pos: vec3<f32>, dir: vec3<f32>) -> bool {
+fn shadow(pos: vec3<f32>, dir: vec3<f32>, max_depth: f32) -> bool {
     var depth = de(pos + dir * settings.epsilon * 10.0);
     // var depth = 0.0;
 
     for (var i = 0; i < settings.max_steps; i++) {
-        let dist = de(pos + dir * depth);
+        let dist = march_de(pos + dir * depth);
         depth += abs(dist);
 
         // hit something
@@ -203,7 +506,7 @@ fn shadow(pos: vec3<f32>, dir: vec3<f32>) -> bool {
         }
 
         // skybox
-        if depth >= settings.max_dist {
+        if depth >= max_depth {
             return false;
         }
     }
@@ -211,60 +514,483 @@ fn shadow(pos: vec3<f32>, dir: vec3<f32>) -> bool {
     return true;
 }
 
-fn run(pos: vec3<f32>, dir: vec3<f32>) -> vec3<f32> {
-    let l = settings.sun_dir;
+// how much of a light `k` times further away than it is wide gets through, for soft shadows
+fn soft_shadow(pos: vec3<f32>, dir: vec3<f32>, max_depth: f32, k: f32) -> f32 {
+    var depth = settings.epsilon * 10.0;
+    var res = 1.0;
+
+    for (var i = 0; i < settings.max_steps && depth < max_depth; i++) {
+        let dist = de(pos + dir * depth);
+        if dist < settings.epsilon {
+            return 0.0;
+        }
+        res = min(res, k * dist / depth);
+        depth += abs(dist);
+    }
+    return clamp(res, 0.0, 1.0);
+}
+
+// Preetham et al. 1999, "A Practical Analytic Model for Daylight"
+
+// brings the sky's luminance in kcd/m^2 down to around 1 at midday
+const SKY_EXPOSURE: f32 = 0.05;
+
+fn perez(cos_theta: f32, gamma: f32, cos_gamma: f32, a: vec3<f32>, b: vec3<f32>, c: vec3<f32>, d: vec3<f32>, e: vec3<f32>) -> vec3<f32> {
+    return (1.0 + a * exp(b / cos_theta)) * (1.0 + c * exp(d * gamma) + e * cos_gamma * cos_gamma);
+}
+
+fn xyY_to_rgb(xyY: vec3<f32>) -> vec3<f32> {
+    let x = xyY.x / xyY.y * xyY.z;
+    let y = xyY.z;
+    let z = (1.0 - xyY.x - xyY.y) / xyY.y * xyY.z;
+    return vec3<f32>(
+        3.2406 * x - 1.5372 * y - 0.4986 * z,
+        -0.9689 * x + 1.8758 * y + 0.0415 * z,
+        0.0557 * x - 0.2040 * y + 1.0570 * z,
+    );
+}
+
+// the model only covers the sun above the horizon, so it's kept there and faded out below
+fn sun_elevation() -> f32 {
+    return max(settings.sun_dir.y, 0.01);
+}
+fn sunset_fade() -> f32 {
+    return smoothstep(-0.1, 0.02, settings.sun_dir.y);
+}
+
+fn sky_color(dir: vec3<f32>) -> vec3<f32> {
+    let t = settings.turbidity;
+    let sun = normalize(vec3<f32>(settings.sun_dir.x, sun_elevation(), settings.sun_dir.z));
+    let theta_s = acos(sun.y);
+
+    // Y, x and y in each component
+    let a = vec3<f32>(0.1787 * t - 1.4630, -0.0193 * t - 0.2592, -0.0167 * t - 0.2608);
+    let b = vec3<f32>(-0.3554 * t + 0.4275, -0.0665 * t + 0.0008, -0.0950 * t + 0.0092);
+    let c = vec3<f32>(-0.0227 * t + 5.3251, -0.0004 * t + 0.2125, -0.0079 * t + 0.2102);
+    let d = vec3<f32>(0.1206 * t - 2.5771, -0.0641 * t - 0.8989, -0.0441 * t - 1.6537);
+    let e = vec3<f32>(-0.0670 * t + 0.3703, -0.0033 * t + 0.0452, -0.0109 * t + 0.0529);
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_s);
+    let zenith_luminance = (4.0453 * t - 4.9710) * tan(chi) - 0.2155 * t + 2.4192;
+    let th = vec4<f32>(theta_s * theta_s * theta_s, theta_s * theta_s, theta_s, 1.0);
+    let zenith_x = dot(vec3<f32>(t * t, t, 1.0), vec3<f32>(
+        dot(vec4<f32>(0.00166, -0.00375, 0.00209, 0.0), th),
+        dot(vec4<f32>(-0.02903, 0.06377, -0.03202, 0.00394), th),
+        dot(vec4<f32>(0.11693, -0.21196, 0.06052, 0.25886), th),
+    ));
+    let zenith_y = dot(vec3<f32>(t * t, t, 1.0), vec3<f32>(
+        dot(vec4<f32>(0.00275, -0.00610, 0.00317, 0.0), th),
+        dot(vec4<f32>(-0.04214, 0.08970, -0.04153, 0.00516), th),
+        dot(vec4<f32>(0.15346, -0.26756, 0.06670, 0.26688), th),
+    ));
+    let zenith = vec3<f32>(zenith_luminance, zenith_x, zenith_y);
+
+    let cos_theta = max(dir.y, 0.01);
+    let cos_gamma = clamp(dot(dir, sun), -1.0, 1.0);
+    let gamma = acos(cos_gamma);
+    let yxy = zenith * perez(cos_theta, gamma, cos_gamma, a, b, c, d, e)
+        / perez(1.0, theta_s, sun.y, a, b, c, d, e);
+
+    let rgb = xyY_to_rgb(vec3<f32>(yxy.y, yxy.z, yxy.x));
+    return max(rgb, vec3<f32>(0.0)) * SKY_EXPOSURE * sunset_fade();
+}
+
+// how much more air the sunlight goes through than when it's straight up, Kasten and Young 1989
+fn air_mass() -> f32 {
+    let elevation = asin(sun_elevation()) * 180.0 / PI;
+    return 1.0 / (sun_elevation() + 0.50572 * pow(elevation + 6.07995, -1.6364));
+}
+// sunlight left after scattering on the way through the atmosphere
+fn sun_color() -> vec3<f32> {
+    let rayleigh = vec3<f32>(0.0465, 0.108, 0.265);
+    let mie = 0.01 * settings.turbidity;
+    return exp(-(rayleigh + mie) * air_mass()) * sunset_fade();
+}
+
+// light reaching the ground from the sun and the sky, bounced back up
+fn ground_color() -> vec3<f32> {
+    let irradiance = sun_color() * sun_elevation() + sky_color(vec3<f32>(0.0, 1.0, 0.0));
+    return settings.ground_albedo * irradiance;
+}
+
+// same as in environment.rs, gl can't ask the texture
+const SPECULAR_MIPS: u32 = 6u;
+
+// same as in environment.wgsl
+fn equirect_uv(dir: vec3<f32>) -> vec2<f32> {
+    return vec2<f32>(atan2(dir.x, dir.z) / (2.0 * PI) + 0.5, 0.5 - asin(clamp(dir.y, -1.0, 1.0)) / PI);
+}
+fn environment_uv(dir: vec3<f32>) -> vec2<f32> {
+    let c = cos(settings.environment_rotation);
+    let s = sin(settings.environment_rotation);
+    return equirect_uv(vec3<f32>(c * dir.x + s * dir.z, dir.y, c * dir.z - s * dir.x));
+}
+fn environment(texture: texture_2d<f32>, dir: vec3<f32>, lod: f32) -> vec3<f32> {
+    let color = textureSampleLevel(texture, environment_sampler, environment_uv(dir), lod).rgb;
+    return color * settings.environment_intensity;
+}
+
+// the split sum's scale and bias to f0 for the environment's reflections, Karis 2014
+fn environment_brdf(f0: vec3<f32>, roughness: f32, ndotv: f32) -> vec3<f32> {
+    let c0 = vec4<f32>(-1.0, -0.0275, -0.572, 0.022);
+    let c1 = vec4<f32>(1.0, 0.0425, 1.04, -0.04);
+    let r = roughness * c0 + c1;
+    let a004 = min(r.x * r.x, exp2(-9.28 * ndotv)) * r.x + r.y;
+    let ab = vec2<f32>(-1.04, 1.04) * a004 + r.zw;
+    return f0 * ab.x + ab.y;
+}
+
+fn background(dir: vec3<f32>) -> vec3<f32> {
+    if settings.environment != 0u {
+        return environment(environment_radiance, dir, 0.0);
+    }
+
+    // blended a little above the horizon so there's no hard line
+    let ground = smoothstep(0.02, -0.02, dir.y);
+    let sky = sky_color(vec3<f32>(dir.x, max(dir.y, 0.0), dir.z));
+
+    var sun_spec = dot(dir, settings.sun_dir) - 1.0 + settings.sun_size;
+    sun_spec = min(exp(sun_spec * settings.sun_sharpness / settings.sun_size), 1.0);
+
+    return mix(clouds(dir, sky + sun_spec * sun_color()), ground_color(), ground);
+}
+
+// a flat layer of clouds over `sky`, drifting with the scene time
+fn clouds(dir: vec3<f32>, sky: vec3<f32>) -> vec3<f32> {
+    if settings.cloud_cover <= 0.0 || dir.y <= 0.0 {
+        return sky;
+    }
+    // where the ray meets the layer, kept finite at the horizon
+    let uv = dir.xz / (dir.y + 0.05) * 1.5 + vec2<f32>(settings.time * 0.02, 0.0);
+    let n = fbm2(NOISE_PERLIN, uv, 6u, 2.0, 0.5) * 0.5 + 0.5;
+    let edge = 1.0 - settings.cloud_cover;
+    let density = smoothstep(edge, edge + 0.3, n) * smoothstep(0.0, 0.1, dir.y);
+
+    // lit by the sun, brighter looking towards it, and by the sky above
+    let sun = sun_color() * (sun_elevation() + phase(dot(dir, settings.sun_dir)));
+    let light = 0.8 * (sun + sky_color(vec3<f32>(0.0, 1.0, 0.0)));
+    return mix(sky, light, density);
+}
 
+// light from the whole sky and ground on a surface facing `n`, leaving out the sun
+fn ambient(n: vec3<f32>) -> vec3<f32> {
+    if settings.environment != 0u {
+        return environment(environment_irradiance, n, 0.0);
+    }
+    let up = normalize(vec3<f32>(n.x, abs(n.y) + 0.3, n.z));
+    return mix(ground_color(), sky_color(up), n.y * 0.5 + 0.5);
+}
+
+fn fog_density(p: vec3<f32>) -> f32 {
+    return settings.fog_density * exp(-settings.fog_falloff * (p.y - settings.fog_height));
+}
+
+// Henyey-Greenstein, haze scatters light mostly forwards
+fn phase(cos_theta: f32) -> f32 {
+    let g = 0.6;
+    let g2 = g * g;
+    return (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * g * cos_theta, 1.5));
+}
+
+// how much of what's behind the fog gets through and the light it scatters towards the ray's start
+struct FogSegment {
+    transmittance: f32,
+    light: vec3<f32>,
+}
+
+// fog over `depth` along the ray, either the exact integral of the height fog
+// or marched with `fog_samples` to find where the sun reaches it
+fn fog(pos: vec3<f32>, dir: vec3<f32>, depth: f32, seed: ptr<function, u32>) -> FogSegment {
+    if settings.fog_density <= 0.0 {
+        return FogSegment(1.0, vec3<f32>(0.0));
+    }
+    // sky from around the horizon, and sunlight scattered towards the camera
+    let horizon = normalize(vec3<f32>(dir.x, 0.1, dir.z));
+    var sky = sky_color(horizon);
+    var sun = sun_color() * phase(dot(dir, settings.sun_dir));
+    // the environment has its own sun, if any, which is already in its irradiance
+    if settings.environment != 0u {
+        sky = environment(environment_irradiance, horizon, 0.0);
+        sun = vec3<f32>(0.0);
+    }
+
+    if settings.fog_samples == 0u {
+        let k = settings.fog_falloff * dir.y;
+        var optical_depth = fog_density(pos) * depth;
+        if abs(k) > 1e-4 {
+            optical_depth *= (1.0 - exp(-k * depth)) / (k * depth);
+        }
+        let transmittance = exp(-optical_depth);
+        return FogSegment(transmittance, (sky + sun) * (1.0 - transmittance));
+    }
+
+    let step = depth / f32(settings.fog_samples);
+    var t = step * random(seed);
+    var transmittance = 1.0;
+    var scattered = vec3<f32>(0.0);
+    for (var i = 0u; i < settings.fog_samples; i++) {
+        let p = pos + dir * t;
+        let absorbed = 1.0 - exp(-fog_density(p) * step);
+
+        var light = sky;
+        if settings.environment == 0u && !shadow(p, settings.sun_dir, settings.max_dist) {
+            light += sun;
+        }
+        scattered += transmittance * absorbed * light;
+        transmittance *= 1.0 - absorbed;
+        t += step;
+    }
+    return FogSegment(transmittance, scattered);
+}
+
+struct LightSample {
+    dir: vec3<f32>,
+    radiance: vec3<f32>,
+    dist: f32,
+}
+
+fn sample_light(light: Light, p: vec3<f32>) -> LightSample {
+    var s: LightSample;
+    if light.kind == LIGHT_DIRECTIONAL {
+        s.dir = light.direction;
+        s.radiance = light.color;
+        s.dist = settings.max_dist;
+        return s;
+    }
+
+    let to_light = light.position - p;
+    s.dist = length(to_light);
+    s.dir = to_light / s.dist;
+
+    // inverse square, smoothly reaching zero at the range
+    let fade = clamp(1.0 - pow(s.dist / light.range, 4.0), 0.0, 1.0);
+    let r = max(light.radius, 0.01);
+    s.radiance = light.color * fade * fade / max(s.dist * s.dist, r * r);
+
+    if light.kind == LIGHT_SPOT {
+        s.radiance *= smoothstep(light.cos_outer, light.cos_inner, dot(-s.dir, light.direction));
+    }
+    // stop shadow rays at the surface of area lights
+    s.dist = max(s.dist - light.radius, 0.0);
+    return s;
+}
+
+// metals reflect in their own colour, everything else a little in white
+fn base_reflectance(color: vec3<f32>) -> vec3<f32> {
+    return mix(vec3<f32>(0.04), color, settings.metallic);
+}
+
+// ggx for light coming from `l`, with highlights from `l_spec` scaled by `spec_scale`
+fn shade(n: vec3<f32>, v: vec3<f32>, l: vec3<f32>, l_spec: vec3<f32>, spec_scale: f32, color: vec3<f32>) -> vec3<f32> {
+    let h = normalize(l_spec + v);
+    let f = fresnelSchlick(dot(v, h), base_reflectance(color));
+    let d = distributionGGX(settings.alpha, n, h);
+    let g = geometrySmith(n, v, l_spec, pow(settings.alpha+1.0,2.0)/8.0);
+
+    let num = g * d * f;
+    let den = 4.0 * dot(v, n) * dot(l_spec, n);
+
+    let kd = (1.0 - f) * (1.0 - settings.metallic);
+    let diffuse = kd * color;
+    let specular = num/max(den, settings.epsilon) * spec_scale;
+
+    return (diffuse + specular) * max(dot(l, n), 0.0);
+}
+
+fn light_contribution(light: Light, p: vec3<f32>, n: vec3<f32>, v: vec3<f32>, color: vec3<f32>) -> vec3<f32> {
+    let s = sample_light(light, p);
+    if dot(s.dir, n) <= 0.0 || all(s.radiance <= vec3<f32>(0.0)) {
+        return vec3<f32>(0.0);
+    }
+
+    var l_spec = s.dir;
+    var spec_scale = 1.0;
+    if light.kind == LIGHT_SPHERE && light.radius > 0.0 {
+        // highlights come from the point on the sphere closest to the reflection,
+        // widened and dimmed to keep the energy the same
+        let to_light = light.position - p;
+        let r = reflect(-v, n);
+        let to_ray = dot(to_light, r) * r - to_light;
+        let closest = to_light + to_ray * clamp(light.radius / max(length(to_ray), 1e-5), 0.0, 1.0);
+        l_spec = normalize(closest);
+        let wider = clamp(settings.alpha + light.radius / (2.0 * length(to_light)), 0.0, 1.0);
+        spec_scale = pow(settings.alpha / wider, 2.0);
+    }
+
+    var visibility = 1.0;
+    if light.shadows != 0u {
+        let start = p + n * settings.epsilon;
+        if light.kind == LIGHT_SPHERE && light.radius > 0.0 {
+            visibility = soft_shadow(start, s.dir, s.dist, s.dist / light.radius);
+        } else if shadow(start, s.dir, s.dist) {
+            visibility = 0.0;
+        }
+    }
+    if visibility <= 0.0 {
+        return vec3<f32>(0.0);
+    }
+
+    return shade(n, v, s.dir, l_spec, spec_scale, color) * s.radiance * visibility;
+}
+
+struct Hit {
+    pos: vec3<f32>,
+    depth: f32,
+    hit: bool,
+    // closest the ray came to the surface and how many steps it took, for glow
+    min_dist: f32,
+    steps: i32,
+}
+
+// `side` is -1 to march inside the fractal, where the distance is negative
+fn march(pos: vec3<f32>, dir: vec3<f32>, side: f32) -> Hit {
     var depth = 0.0;
+    var min_dist = settings.max_dist;
+    var p = pos;
     var i = 0;
-
-    var p: vec3<f32>;
     for (; i < settings.max_steps; i++) {
         p = pos + dir * depth;
-        let dist = de(p);
+        let dist = march_de(p) * side;
+        min_dist = min(min_dist, dist);
         
         if dist < settings.epsilon {
-            // hit
-            break;
+            return Hit(p, depth, true, min_dist, i);
         }
         depth += dist;
         
-        // background color
         if depth >= settings.max_dist {
-            var sun_spec = dot(dir, l) - 1.0 + settings.sun_size;
-			sun_spec = min(exp(sun_spec * settings.sun_sharpness / settings.sun_size), 1.0);
-			return vec3<f32>(0.02, 0.4, 0.6) + sun_spec;
+            return Hit(p, settings.max_dist, false, min_dist, i);
         }
     }
-        
-    let n = calc_normal(p, settings.epsilon);
-    let v = -dir;
-    let h = normalize(l + v);
+    return Hit(p, depth, true, min_dist, i);
+}
 
-    var f = fresnelSchlick(dot(v, h), vec3<f32>(0.04));
-    var light = 0.0;
-    let in_shadow = shadow(p + n * settings.epsilon, l);
-    if !in_shadow {
-        light = dot(l, n) * 2.0;
+// light picked up along the way, more in crevices the ray had to step through
+// and a halo where it only just missed
+fn glow(hit: Hit) -> vec3<f32> {
+    if settings.glow_intensity <= 0.0 {
+        return vec3<f32>(0.0);
     }
-    else {
-        f = vec3<f32>(0.0);
+    var amount = f32(hit.steps) / f32(settings.max_steps);
+    if !hit.hit {
+        amount += exp(-settings.glow_falloff * max(hit.min_dist, 0.0));
     }
-    
-    let ks = f;
-    let kd = 1.0 - ks;
+    return settings.glow_color * settings.glow_intensity * amount;
+}
 
-    let color = (n + 1.0) / 2.0;
-    let lambert = color / PI;
-    let d = distributionGGX(settings.alpha, n, h);
-    let g = geometrySmith(n, v, l, pow(settings.alpha+1.0,2.0)/8.0);
+// rock with snow settled on the flatter ground above the snow line
+fn terrain_color(p: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    let rock = vec3<f32>(0.3, 0.25, 0.2);
+    let snow = vec3<f32>(0.9, 0.92, 0.95);
 
-    let num = g * d * f;
-    let den = 4.0 * dot(v, n) * dot(l, n);
+    // ragged so it doesn't look like a contour line
+    let ragged = 0.1 * value_noise2(p.xz * 8.0 / settings.terrain_scale);
+    let line = settings.terrain_height * (settings.snow_line + ragged - 1.0);
+    let high = smoothstep(line - 0.05, line + 0.05, p.y);
+    let flatness = smoothstep(0.6, 0.8, n.y);
+    return mix(rock, snow, high * flatness);
+}
 
-    let diffuse = kd * color;
-    let specular = num/max(den, settings.epsilon);
+fn albedo(p: vec3<f32>, n: vec3<f32>) -> vec3<f32> {
+    if settings.scene == 2u {
+        return terrain_color(p, n);
+    }
+    return (n + 1.0) / 2.0;
+}
+
+// light leaving a solid surface, with `reflections` of the environment unless they're traced
+fn surface(p: vec3<f32>, n: vec3<f32>, v: vec3<f32>, color: vec3<f32>, reflections: bool) -> vec3<f32> {
+    var total = vec3<f32>(0.0);
+    let count = min(settings.light_count, arrayLength(&lights));
+    for (var i = 0u; i < count; i++) {
+        total += light_contribution(lights[i], p, n, v, color);
+    }
 
-    return (diffuse + specular) * max(light, 0.1);
+    var result = total + color * (1.0 - settings.metallic) * ambient(n) + color * settings.emission;
+    if reflections && settings.environment != 0u {
+        // reflections of the environment, blurrier the rougher the surface
+        let roughness = sqrt(settings.alpha);
+        let lod = roughness * f32(SPECULAR_MIPS - 1u);
+        let reflection = environment(environment_specular, reflect(-v, n), lod);
+        result += reflection * environment_brdf(base_reflectance(color), roughness, max(dot(n, v), 0.0));
+    }
+    return result;
+}
+
+// follows the ray through up to `bounces` mirror reflections or refractions. solid surfaces pass
+// on the light their fresnel reflects, glass the light it lets through, and when there are no
+// bounces left the ray carries on to the background
+fn run(pos: vec3<f32>, dir: vec3<f32>, seed: ptr<function, u32>) -> vec3<f32> {
+    var ray_pos = pos;
+    var ray_dir = dir;
+    var inside = false;
+    var result = vec3<f32>(0.0);
+    var throughput = vec3<f32>(1.0);
+
+    for (var bounce = 0u; bounce <= settings.bounces; bounce++) {
+        let side = select(1.0, -1.0, inside);
+        let hit = march(ray_pos, ray_dir, side);
+        if inside {
+            // beer-lambert
+            throughput *= exp(-settings.absorption * hit.depth);
+        } else {
+            let segment = fog(ray_pos, ray_dir, hit.depth, seed);
+            result += throughput * segment.light;
+            throughput *= segment.transmittance;
+            result += throughput * glow(hit);
+        }
+        if !hit.hit {
+            return result + throughput * background(ray_dir);
+        }
+
+        // facing the side the ray is on, the colour still comes from the outside
+        let n = calc_normal(hit.pos, settings.epsilon) * side;
+        let v = -ray_dir;
+        let color = albedo(hit.pos, n * side);
+        let last = bounce == settings.bounces;
+
+        let solid = select(1.0 - settings.transmission, 0.0, inside);
+        if solid > 0.0 {
+            // partly glass surfaces only follow the refraction
+            let traced = !last && solid >= 1.0;
+            result += throughput * solid * surface(hit.pos, n, v, color, !traced);
+            if traced {
+                throughput *= fresnelSchlick(max(dot(n, v), 0.0), base_reflectance(color));
+                ray_pos = hit.pos + n * settings.epsilon * 10.0;
+                ray_dir = reflect(ray_dir, n);
+                continue;
+            }
+        }
+        if solid >= 1.0 {
+            break;
+        }
+        throughput *= 1.0 - solid;
+
+        let reflected = reflect(ray_dir, n);
+        let refracted = refract(ray_dir, n, select(1.0 / settings.ior, settings.ior, inside));
+        if all(refracted == vec3<f32>(0.0)) {
+            // total internal reflection
+            ray_pos = hit.pos + n * settings.epsilon * 10.0;
+            ray_dir = reflected;
+        } else {
+            // schlick needs the angle on the thinner side
+            let cos_theta = select(dot(n, v), dot(-n, refracted), inside);
+            let r0 = pow((settings.ior - 1.0) / (settings.ior + 1.0), 2.0);
+            let f = fresnelSchlick(max(cos_theta, 0.0), vec3<f32>(r0));
+            if !inside {
+                // only the background shows in the glass, tracing both ways would branch
+                result += throughput * f * background(reflected);
+            }
+            throughput *= 1.0 - f;
+            ray_pos = hit.pos - n * settings.epsilon * 10.0;
+            ray_dir = refracted;
+            inside = !inside;
+        }
+        if last {
+            result += throughput * background(ray_dir);
+        }
+    }
+    return result;
 }
 
 struct VertexIn {
@@ -286,11 +1012,130 @@ fn vs_main(
     return out;
 }
 
+fn random(seed: ptr<function, u32>) -> f32 {
+    *seed = hash(*seed);
+    return f32(*seed) / 4294967295.0;
+}
+fn random_in_disk(seed: ptr<function, u32>) -> vec2<f32> {
+    let r = sqrt(random(seed));
+    let theta = 2.0 * PI * random(seed);
+    return r * vec2<f32>(cos(theta), sin(theta));
+}
+
+struct Ray {
+    pos: vec3<f32>,
+    dir: vec3<f32>,
+    // where rays through the lens converge for depth of field
+    focus: vec3<f32>,
+    // direction the eyes are offset in for stereo
+    side: vec3<f32>,
+}
+
+fn camera_ray(uv: vec2<f32>) -> Ray {
+    let origin = (camera.matrix * vec4<f32>(0.0, 0.0, 0.0, 1.0)).xyz;
+    let right = normalize(camera.matrix[0].xyz);
+    let up = normalize(camera.matrix[1].xyz);
+    let forward = normalize(camera.matrix[2].xyz);
+    let aspect = length(camera.matrix[0].xyz) / length(camera.matrix[1].xyz);
+
+    var ray: Ray;
+    ray.side = right;
+    switch camera.projection {
+        // orthographic
+        case 1u {
+            let half_height = camera.projection_param / 2.0;
+            ray.pos = origin + (right * uv.x * aspect + up * uv.y) * half_height;
+            ray.dir = forward;
+            ray.focus = ray.pos + ray.dir * camera.focus_dist;
+        }
+        // equirectangular
+        case 2u {
+            let lon = uv.x * PI;
+            let lat = uv.y * PI / 2.0;
+            ray.pos = origin;
+            ray.dir = right * cos(lat) * sin(lon) + up * sin(lat) + forward * cos(lat) * cos(lon);
+            ray.focus = ray.pos + ray.dir * camera.focus_dist;
+            // omni-directional stereo: the eyes turn with the longitude
+            ray.side = right * cos(lon) - forward * sin(lon);
+        }
+        // stereographic fisheye
+        case 3u {
+            let p = vec2<f32>(uv.x * aspect, uv.y);
+            let r = length(p);
+            let theta = 2.0 * atan(r * tan(camera.projection_param / 4.0));
+            var side = vec2<f32>(0.0);
+            if r > 0.0 {
+                side = p / r * sin(theta);
+            }
+            ray.pos = origin;
+            ray.dir = right * side.x + up * side.y + forward * cos(theta);
+            ray.focus = ray.pos + ray.dir * camera.focus_dist;
+        }
+        // perspective
+        default {
+            // point on the plane one unit in front of the camera
+            let plane = (camera.matrix * vec4<f32>(uv.x, uv.y, 1.0, 1.0)).xyz - origin;
+            ray.pos = origin;
+            ray.dir = normalize(plane);
+            ray.focus = ray.pos + plane * camera.focus_dist;
+        }
+    }
+    return ray;
+}
+
+// `eye` is -1 for the left eye, 1 for the right and 0 without stereo
+fn render_eye(uv: vec2<f32>, eye: f32, seed: ptr<function, u32>) -> vec3<f32> {
+    var ray = camera_ray(uv);
+
+    let offset = ray.side * eye * camera.ipd / 2.0;
+    ray.pos += offset;
+    ray.focus += offset;
+
+    if camera.aperture > 0.0 {
+        // thin lens: every ray through the lens meets at the focal point
+        let lens = random_in_disk(seed) * camera.aperture / 2.0;
+        let right = normalize(camera.matrix[0].xyz);
+        let up = normalize(camera.matrix[1].xyz);
+
+        ray.pos += right * lens.x + up * lens.y;
+        ray.dir = normalize(ray.focus - ray.pos);
+    }
+    return run(ray.pos, ray.dir, seed);
+}
+
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let pos = (camera * vec4<f32>(0.0, 0.0, 0.0, 1.0)).xyz;
-    let dir = normalize(
-        (camera * vec4<f32>(in.uv.x, in.uv.y, 1.0, 1.0)).xyz - pos
-        );
-    return vec4<f32>(run(pos, dir), 1.0);
+    let pixel = vec2<u32>(in.clip_position.xy);
+    var seed = hash(pixel.x + hash(pixel.y + hash(camera.sample)));
+
+    var color: vec3<f32>;
+    let uv = in.uv;
+    switch camera.stereo {
+        // side by side
+        case 1u {
+            if uv.x < 0.0 {
+                color = render_eye(vec2<f32>(uv.x * 2.0 + 1.0, uv.y), -1.0, &seed);
+            } else {
+                color = render_eye(vec2<f32>(uv.x * 2.0 - 1.0, uv.y), 1.0, &seed);
+            }
+        }
+        // top bottom
+        case 2u {
+            if uv.y > 0.0 {
+                color = render_eye(vec2<f32>(uv.x, uv.y * 2.0 - 1.0), -1.0, &seed);
+            } else {
+                color = render_eye(vec2<f32>(uv.x, uv.y * 2.0 + 1.0), 1.0, &seed);
+            }
+        }
+        // anaglyph
+        case 3u {
+            let left = render_eye(uv, -1.0, &seed);
+            let right = render_eye(uv, 1.0, &seed);
+            color = vec3<f32>(left.r, right.g, right.b);
+        }
+        default {
+            color = render_eye(uv, 0.0, &seed);
+        }
+    }
+    return vec4<f32>(color, 1.0 / f32(camera.sample + 1u));
 }
@@ -0,0 +1,206 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    bookmark::Bookmark, movement::Movement, orbit::Orbit, Camera, Controller, Flight, SceneData,
+    SettingsUniform,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Frame {
+    pub dt: f32,
+    pub controller: Controller,
+}
+
+/// Input for every frame along with where it started so it can be played back exactly.
+///
+/// One off actions like switching scenes aren't recorded.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Recording {
+    pub start: Bookmark,
+    pub orbit: Option<Orbit>,
+    pub movement: Movement,
+    pub paused: bool,
+    pub frames: Vec<Frame>,
+}
+impl Recording {
+    pub fn new(start: Bookmark, orbit: Option<Orbit>, movement: Movement, paused: bool) -> Self {
+        Self {
+            start,
+            orbit,
+            movement,
+            paused,
+            frames: Vec::new(),
+        }
+    }
+    pub fn duration(&self) -> f32 {
+        self.frames.iter().map(|frame| frame.dt).sum()
+    }
+
+    /// Plays the recording without a window, calling `frame` with the view after every frame.
+    ///
+    /// Use with the offline renderer to get what was on screen, with its scene data so the camera
+    /// collides with the same heightmap and mesh.
+    pub fn simulate(
+        &self,
+        fixed_timestep: Option<f32>,
+        data: &SceneData,
+        mut frame: impl FnMut(&Camera, &SettingsUniform),
+    ) {
+        let mut camera = self.start.camera;
+        let mut settings = self.start.settings;
+        let mut orbit = self.orbit;
+        let mut movement = self.movement;
+        for Frame { dt, controller } in &self.frames {
+            Flight {
+                camera: &mut camera,
+                orbit: &mut orbit,
+                movement: &mut movement,
+                settings: &mut settings,
+                paused: self.paused,
+            }
+            .step(controller, data, fixed_timestep.unwrap_or(*dt));
+            frame(&camera, &settings);
+        }
+    }
+
+    pub fn load(path: impl AsRef<std::path::Path>) -> Result<Self, Box<dyn std::error::Error>> {
+        let file = std::fs::read_to_string(path)?;
+        Ok(ron::from_str(&file)?)
+    }
+    pub fn save(
+        &self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let file = ron::ser::to_string(self)?;
+        std::fs::write(path, file)?;
+        Ok(())
+    }
+}
+
+/// The frames of a recording in order.
+#[derive(Clone, Debug)]
+pub struct Replay {
+    recording: Recording,
+    next: usize,
+    fixed_timestep: Option<f32>,
+}
+impl Replay {
+    pub fn new(recording: Recording, fixed_timestep: Option<f32>) -> Self {
+        Self {
+            recording,
+            next: 0,
+            fixed_timestep,
+        }
+    }
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+}
+impl Iterator for Replay {
+    type Item = Frame;
+
+    fn next(&mut self) -> Option<Frame> {
+        let mut frame = *self.recording.frames.get(self.next)?;
+        self.next += 1;
+        if let Some(dt) = self.fixed_timestep {
+            frame.dt = dt;
+        }
+        Some(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::na;
+
+    fn controllers() -> impl Iterator<Item = Controller> {
+        (0..30).map(|i| Controller {
+            translation: na::vector![(i as f32 * 0.3).sin(), 0.2, 1.0],
+            rotation: na::vector![0.5, -0.25],
+            speed: (i as f32 * 0.1).cos(),
+            looking: i % 3 == 0,
+            mouse: na::vector![4.0, -2.0],
+            ..Default::default()
+        })
+    }
+
+    type View = (Camera, SettingsUniform);
+
+    // flies the way `RayMarcher::update` does while recording, keeping the view after every frame
+    fn record(orbit: Option<Orbit>, data: &SceneData) -> (Recording, Vec<View>) {
+        let start = Bookmark {
+            camera: Camera::default(),
+            settings: crate::default_settings(),
+        };
+        let mut recording = Recording::new(start, orbit, Movement::default(), false);
+
+        let (mut camera, mut settings) = (start.camera, start.settings);
+        let (mut orbit, mut movement) = (orbit, Movement::default());
+        let mut views = Vec::new();
+        for (i, controller) in controllers().enumerate() {
+            let dt = 0.01 + 0.005 * (i % 4) as f32;
+            recording.frames.push(Frame { dt, controller });
+            Flight {
+                camera: &mut camera,
+                orbit: &mut orbit,
+                movement: &mut movement,
+                settings: &mut settings,
+                paused: false,
+            }
+            .step(&controller, data, dt);
+            views.push((camera, settings));
+        }
+        (recording, views)
+    }
+
+    // plays the frames back the way `RayMarcher::update` does while replaying
+    fn replay(recording: Recording, fixed_timestep: Option<f32>, data: &SceneData) -> Vec<View> {
+        let (mut camera, mut settings) = (recording.start.camera, recording.start.settings);
+        let (mut orbit, mut movement) = (recording.orbit, recording.movement);
+        let paused = recording.paused;
+        Replay::new(recording, fixed_timestep)
+            .map(|Frame { dt, controller }| {
+                Flight {
+                    camera: &mut camera,
+                    orbit: &mut orbit,
+                    movement: &mut movement,
+                    settings: &mut settings,
+                    paused,
+                }
+                .step(&controller, data, dt);
+                (camera, settings)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn saved_recordings_replay_the_recorded_views() {
+        let data = SceneData::default();
+        let path = std::env::temp_dir().join(format!("recording-{}.ron", std::process::id()));
+        for orbit in [None, Some(Orbit::from_camera(&Camera::default(), 2.0))] {
+            let (recording, live) = record(orbit, &data);
+            assert_ne!(live.last().unwrap().0, recording.start.camera);
+
+            recording.save(&path).unwrap();
+            let loaded = Recording::load(&path).unwrap();
+            assert_eq!(loaded, recording);
+            assert_eq!(replay(loaded.clone(), None, &data), live);
+
+            let mut simulated = Vec::new();
+            loaded.simulate(None, &data, |camera, settings| {
+                simulated.push((*camera, *settings))
+            });
+            assert_eq!(simulated, live);
+
+            // a fixed timestep goes elsewhere, but the same way every time
+            let fixed = Some(1.0 / 60.0);
+            let mut simulated = Vec::new();
+            loaded.simulate(fixed, &data, |camera, settings| {
+                simulated.push((*camera, *settings))
+            });
+            assert_eq!(replay(loaded, fixed, &data), simulated);
+        }
+        std::fs::remove_file(path).unwrap();
+    }
+}
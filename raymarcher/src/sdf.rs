@@ -0,0 +1,379 @@
+// CPU side versions of the distance estimators in raymarcher.wgsl.
+// Keep these in sync with the shader.
+
+use crate::{domain::*, mesh::MeshSdf, na, noise, pipeline::SettingsUniform, SceneData};
+
+const K: f32 = 0.052_359_877; // 2pi / 120
+
+fn real_mod(dividend: na::Vector3<f32>, divisor: f32) -> na::Vector3<f32> {
+    dividend.map(|x| x - divisor * (x / divisor).floor())
+}
+
+fn sphere_distance(p: na::Vector3<f32>) -> f32 {
+    p.magnitude() - 1.0
+}
+fn box_distance(p: na::Vector3<f32>, b: na::Vector3<f32>) -> f32 {
+    let q = p.abs() - b;
+    q.sup(&na::Vector3::zeros()).magnitude() + q.max().min(0.0)
+}
+
+const LEN: f32 = 3.0;
+fn cross_distance(p: na::Vector3<f32>) -> f32 {
+    let da = box_distance(p, na::vector![LEN, 1.0, 1.0]);
+    let db = box_distance(p.yzx(), na::vector![1.0, LEN, 1.0]);
+    let dc = box_distance(p.zxy(), na::vector![1.0, 1.0, LEN]);
+    da.min(db.min(dc))
+}
+
+const MENGER_SPONGE_ITERATIONS: usize = 5;
+fn menger_sponge(p: na::Vector3<f32>) -> f32 {
+    let mut pr = p;
+
+    let mut d = box_distance(p, na::Vector3::repeat(1.0));
+    let mut s = 1.0;
+    for _ in 0..MENGER_SPONGE_ITERATIONS {
+        let a = real_mod(pr * s, 2.0).add_scalar(-1.0);
+        s *= 3.0;
+        let r = (a.abs() * -3.0).add_scalar(1.0);
+
+        let c = cross_distance(r) / s;
+        d = d.max(c);
+
+        pr = pr.add_scalar(0.1 * s);
+    }
+    d
+}
+
+const AMP: f32 = 4.5;
+const MID: f32 = 5.5;
+const ITERATIONS: usize = 15;
+fn mandelbulb(pos: na::Vector3<f32>, time: f32) -> f32 {
+    let power = AMP * (K * time).sin() + MID;
+
+    let mut z = pos;
+    let mut dr = 1.0;
+    let mut r = 0.0;
+    for _ in 0..ITERATIONS {
+        r = z.magnitude();
+        if r > 2.0 {
+            break;
+        }
+
+        // convert to polar coordinates, at the origin the angles don't matter since z^power is 0
+        let (mut theta, mut phi) = if r > 0.0 {
+            ((z.z / r).acos(), z.y.atan2(z.x))
+        } else {
+            (0.0, 0.0)
+        };
+        dr = r.powf(power - 1.0) * power * dr + 1.0;
+
+        // scale and rotate the point
+        let zr = r.powf(power);
+        theta *= power;
+        phi *= power;
+
+        // convert back to cartesian coordinates
+        z = na::vector![
+            theta.sin() * phi.cos(),
+            phi.sin() * theta.sin(),
+            theta.cos()
+        ] * zr;
+        z += pos;
+    }
+    // the origin never escapes, it's inside
+    if r > 0.0 {
+        0.5 * r.ln() * r / dr
+    } else {
+        0.0
+    }
+}
+
+const TERRAIN_LACUNARITY: f32 = 2.0;
+const TERRAIN_GAIN: f32 = 0.5;
+
+fn terrain_level(settings: &SettingsUniform, data: &SceneData, xz: na::Vector2<f32>) -> f32 {
+    if let Some(heightmap) = &data.heightmap {
+        return heightmap.sample((xz / settings.terrain_scale).add_scalar(0.5));
+    }
+    let n = noise::fbm2(
+        noise::Noise::Perlin,
+        xz / settings.terrain_scale,
+        settings.terrain_octaves,
+        TERRAIN_LACUNARITY,
+        TERRAIN_GAIN,
+    );
+    n * 0.5 + 0.5
+}
+fn terrain_slope(settings: &SettingsUniform, data: &SceneData) -> f32 {
+    if let Some(heightmap) = &data.heightmap {
+        let (width, height) = heightmap.size();
+        let texels = width.max(height) as f32 / settings.terrain_scale;
+        return settings.terrain_height * heightmap.slope() * texels;
+    }
+    let octaves = settings.terrain_octaves as f32;
+    let total = 2.0 * (1.0 - TERRAIN_GAIN.powf(octaves));
+    let fbm_slope = noise::PERLIN_SLOPE * octaves / total.max(1e-6);
+    settings.terrain_height * 0.5 * fbm_slope / settings.terrain_scale
+}
+fn terrain(settings: &SettingsUniform, data: &SceneData, p: na::Vector3<f32>) -> f32 {
+    let ground = settings.terrain_height * (terrain_level(settings, data, p.xz()) - 1.0);
+    let slope = terrain_slope(settings, data);
+    ((p.y - ground) / (1.0 + slope * slope).sqrt()).max(p.y)
+}
+
+fn mesh_distance(settings: &SettingsUniform, mesh: &MeshSdf, p: na::Vector3<f32>) -> f32 {
+    let local = (p - na::Vector3::from(settings.mesh_position)) / settings.mesh_scale;
+    mesh.distance(local.into()) * settings.mesh_scale
+}
+
+fn scene_distance(settings: &SettingsUniform, data: &SceneData, p: na::Vector3<f32>) -> f32 {
+    let d = match settings.scene {
+        0 => mandelbulb(p, settings.time),
+        1 => menger_sponge(p),
+        2 => terrain(settings, data, p),
+        _ => sphere_distance(p),
+    };
+    match &data.mesh {
+        Some(mesh) => d.min(mesh_distance(settings, mesh, p)),
+        None => d,
+    }
+}
+
+fn rotate(x: f32, y: f32, a: f32) -> (f32, f32) {
+    let (s, c) = a.sin_cos();
+    (c * x - s * y, s * x + c * y)
+}
+
+pub fn de(settings: &SettingsUniform, data: &SceneData, p: na::Point3<f32>) -> f32 {
+    let mut q = p.coords;
+    let mut stretch = 1.0;
+    let mut displacement = 0.0;
+    let mut displacement_slope = 0.0;
+    for op in &settings.domain {
+        let a = na::Vector3::from(op.a);
+        match op.kind {
+            DOMAIN_REPEAT => {
+                for i in 0..3 {
+                    if a[i] > 0.0 {
+                        let limit = if op.b[i] < 0.0 { 1e9 } else { op.b[i] };
+                        q[i] -= a[i] * (q[i] / a[i]).round_ties_even().clamp(-limit, limit);
+                    }
+                }
+            }
+            DOMAIN_MIRROR => {
+                let d = q.dot(&a) - op.param;
+                q -= a * 2.0 * d.min(0.0);
+            }
+            DOMAIN_POLAR => {
+                let sector = std::f32::consts::TAU / op.param.max(1.0);
+                let angle = (q.z.atan2(q.x) + sector / 2.0).rem_euclid(sector) - sector / 2.0;
+                let r = q.xz().magnitude();
+                q = na::vector![r * angle.cos(), q.y, r * angle.sin()];
+            }
+            DOMAIN_TWIST => {
+                (q.x, q.z) = rotate(q.x, q.z, op.param * q.y);
+                // largest stretch of a shear by s
+                let s = op.param.abs() * q.xz().magnitude();
+                stretch *= 0.5 * (s + (s * s + 4.0).sqrt());
+            }
+            DOMAIN_BEND => {
+                (q.x, q.y) = rotate(q.x, q.y, op.param * q.x);
+                // the shear can line up with x here, so it can stretch by all of it
+                stretch *= 1.0 + op.param.abs() * q.xy().magnitude();
+            }
+            DOMAIN_DISPLACE => {
+                displacement += op.param * noise::value3(q * a.x);
+                displacement_slope += stretch * (op.param * a.x).abs() * noise::VALUE_SLOPE;
+            }
+            _ => {}
+        }
+    }
+    (scene_distance(settings, data, q) + displacement) / (stretch + displacement_slope)
+}
+
+/// Surface normal from the gradient of the distance estimator.
+pub fn normal(
+    settings: &SettingsUniform,
+    data: &SceneData,
+    p: na::Point3<f32>,
+    h: f32,
+) -> na::Vector3<f32> {
+    let d =
+        |offset: na::Vector3<f32>| de(settings, data, p + offset) - de(settings, data, p - offset);
+    let n = na::vector![
+        d(na::Vector3::x() * h),
+        d(na::Vector3::y() * h),
+        d(na::Vector3::z() * h)
+    ];
+    n.try_normalize(0.0).unwrap_or_else(na::Vector3::y)
+}
+
+/// Marches a single ray the same way the shader does and returns the distance to the hit.
+pub fn march(
+    settings: &SettingsUniform,
+    data: &SceneData,
+    pos: na::Point3<f32>,
+    dir: na::Vector3<f32>,
+) -> Option<f32> {
+    let mut depth = 0.0;
+    for _ in 0..settings.max_steps {
+        let dist = de(settings, data, pos + dir * depth);
+        if dist < settings.epsilon {
+            return Some(depth);
+        }
+        depth += dist;
+        if depth >= settings.max_dist {
+            return None;
+        }
+    }
+    // the shader shades running out of steps as a hit
+    Some(depth)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mandelbulb_at_the_origin() {
+        let settings = crate::default_settings();
+        let d = de(&settings, &SceneData::default(), na::Point3::origin());
+        assert_eq!(d, 0.0);
+        let n = normal(&settings, &SceneData::default(), na::Point3::origin(), 1e-3);
+        assert!(n.iter().all(|x| x.is_finite()));
+    }
+
+    #[test]
+    fn normal_where_the_gradient_rounds_away() {
+        let settings = SettingsUniform {
+            scene: 3,
+            ..crate::default_settings()
+        };
+        // the offsets are lost next to a coordinate this big
+        let p = na::point![1e8, 0.0, 0.0];
+        let n = normal(&settings, &SceneData::default(), p, 1e-3);
+        assert_eq!(n, na::Vector3::y());
+    }
+
+    fn with_domain(scene: u32, ops: &[DomainOp]) -> SettingsUniform {
+        let mut settings = SettingsUniform {
+            scene,
+            ..crate::default_settings()
+        };
+        settings.set_domain(ops);
+        settings
+    }
+
+    // spread through a few units around the origin, the same every run
+    fn points() -> impl Iterator<Item = na::Point3<f32>> {
+        (0..500).map(|i| {
+            let x = i as f32;
+            let wrap = |t: f32| (t.fract() * 2.0 - 1.0) * 3.0;
+            na::point![
+                wrap(x * 0.618_034),
+                wrap(x * 0.414_214),
+                wrap(x * 0.732_051)
+            ]
+        })
+    }
+
+    fn assert_close(a: f32, b: f32, at: na::Point3<f32>) {
+        assert!((a - b).abs() < 1e-3, "{a} and {b} at {at}");
+    }
+
+    #[test]
+    fn repeat_is_periodic() {
+        let settings = with_domain(1, &[DomainOp::repeat(na::vector![3.0, 0.0, 2.5])]);
+        let de = |p| de(&settings, &SceneData::default(), p);
+        for p in points() {
+            assert_close(de(p), de(p + na::vector![3.0, 0.0, 0.0]), p);
+            assert_close(de(p), de(p + na::vector![-6.0, 0.0, 5.0]), p);
+        }
+    }
+
+    #[test]
+    fn repeat_limited_stops_at_the_limit() {
+        let settings = with_domain(
+            3,
+            &[DomainOp::repeat_limited(
+                na::vector![3.0, 0.0, 0.0],
+                na::vector![2.0, 0.0, 0.0],
+            )],
+        );
+        let de = |x| de(&settings, &SceneData::default(), na::point![x, 0.0, 0.0]);
+        // copies of the unit sphere at -6, -3, 0, 3 and 6 and nothing past them
+        for x in [-6.0, -3.0, 0.0, 3.0, 6.0] {
+            assert_close(de(x), -1.0, na::point![x, 0.0, 0.0]);
+        }
+        assert_close(de(9.0), 2.0, na::point![9.0, 0.0, 0.0]);
+        assert_close(de(-12.0), 5.0, na::point![-12.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn mirror_is_symmetric() {
+        let normal = na::vector![1.0, 1.0, 0.0].normalize();
+        let offset = 0.3;
+        let mirrored = with_domain(0, &[DomainOp::mirror(normal, offset)]);
+        let plain = crate::default_settings();
+        let data = SceneData::default();
+        for p in points() {
+            let d = p.coords.dot(&normal) - offset;
+            let reflected = p - normal * 2.0 * d;
+            assert_close(de(&mirrored, &data, p), de(&mirrored, &data, reflected), p);
+            // the side the normal points to is left alone
+            let outside = if d >= 0.0 { p } else { reflected };
+            assert_close(de(&mirrored, &data, p), de(&plain, &data, outside), p);
+        }
+    }
+
+    #[test]
+    fn polar_is_rotationally_periodic() {
+        let count = 5;
+        let settings = with_domain(1, &[DomainOp::polar(count)]);
+        let rot = na::Rotation3::from_axis_angle(
+            &na::Vector3::y_axis(),
+            std::f32::consts::TAU / count as f32,
+        );
+        let de = |p| de(&settings, &SceneData::default(), p);
+        for p in points() {
+            assert_close(de(p), de(rot * p), p);
+            assert_close(de(p), de(rot.inverse() * p), p);
+        }
+    }
+
+    // a step of the estimated distance in any direction never ends up inside the surface
+    fn assert_safe_steps(settings: &SettingsUniform) {
+        let data = SceneData::default();
+        let directions = points()
+            .take(40)
+            .filter_map(|p| p.coords.try_normalize(1e-3))
+            .collect::<Vec<_>>();
+        for p in points() {
+            let d = de(settings, &data, p);
+            if d <= 0.0 {
+                continue;
+            }
+            for dir in &directions {
+                for t in [0.5, 1.0] {
+                    let q = p + dir * d * t;
+                    let dq = de(settings, &data, q);
+                    assert!(
+                        dq > -1e-4,
+                        "stepping {d} from {p} lands inside at {q} ({dq})"
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn twist_bend_and_displace_dont_overstep() {
+        assert_safe_steps(&with_domain(1, &[DomainOp::twist(1.5)]));
+        assert_safe_steps(&with_domain(1, &[DomainOp::bend(0.8)]));
+        assert_safe_steps(&with_domain(3, &[DomainOp::displace(0.3, 4.0)]));
+        assert_safe_steps(&with_domain(
+            1,
+            &[DomainOp::twist(1.0), DomainOp::displace(0.1, 3.0)],
+        ));
+    }
+}
@@ -1,5 +1,73 @@
 use raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 
+pub fn instance() -> wgpu::Instance {
+    // The instance is a handle to our GPU
+    // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::all(),
+        dx12_shader_compiler: Default::default(),
+        flags: wgpu::InstanceFlags::default(),
+        gles_minor_version: wgpu::Gles3MinorVersion::default(),
+    })
+}
+
+/// Requests a device that can draw to `compatible_surface`, or any device if there is no surface.
+///
+/// `optional_features` are only enabled if the adapter supports them.
+pub async fn request_device(
+    instance: &wgpu::Instance,
+    compatible_surface: Option<&wgpu::Surface>,
+    optional_features: wgpu::Features,
+) -> (wgpu::Adapter, wgpu::Device, wgpu::Queue) {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface,
+            force_fallback_adapter: false,
+        })
+        .await
+        .unwrap();
+
+    device_for(adapter, optional_features).await
+}
+
+/// Requests a device on a software adapter, which renders the same on every machine.
+///
+/// Returns `None` if there isn't one.
+pub async fn request_fallback_device(
+    instance: &wgpu::Instance,
+    optional_features: wgpu::Features,
+) -> Option<(wgpu::Adapter, wgpu::Device, wgpu::Queue)> {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::default(),
+            compatible_surface: None,
+            force_fallback_adapter: true,
+        })
+        .await?;
+
+    Some(device_for(adapter, optional_features).await)
+}
+
+async fn device_for(
+    adapter: wgpu::Adapter,
+    optional_features: wgpu::Features,
+) -> (wgpu::Adapter, wgpu::Device, wgpu::Queue) {
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                features: adapter.features() & optional_features,
+                limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None, // Trace path
+        )
+        .await
+        .unwrap();
+
+    (adapter, device, queue)
+}
+
 pub struct WgpuContext<W: HasRawDisplayHandle + HasRawWindowHandle> {
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
@@ -12,14 +80,7 @@ where
     W: HasRawDisplayHandle + HasRawWindowHandle,
 {
     pub async fn new(window: W, size: (u32, u32)) -> Self {
-        // The instance is a handle to our GPU
-        // Backends::all => Vulkan + Metal + DX12 + Browser WebGPU
-        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::all(),
-            dx12_shader_compiler: Default::default(),
-            flags: wgpu::InstanceFlags::default(),
-            gles_minor_version: wgpu::Gles3MinorVersion::default(),
-        });
+        let instance = instance();
 
         // # Safety
         //
@@ -27,26 +88,8 @@ where
         // State owns the window so this should be safe.
         let surface = unsafe { instance.create_surface(&window) }.unwrap();
 
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    label: None,
-                },
-                None, // Trace path
-            )
-            .await
-            .unwrap();
+        let (adapter, device, queue) =
+            request_device(&instance, Some(&surface), wgpu::Features::empty()).await;
 
         let surface_caps = surface.get_capabilities(&adapter);
         log::info!("Surface capabilities: {:#?}", surface_caps);
@@ -0,0 +1,406 @@
+// Renders every scene on a software adapter and compares the images to the ones in tests/golden.
+// Run with UPDATE_GOLDEN=1 to replace them after an intended change.
+
+use std::path::Path;
+
+use raymarcher::{
+    distance_cache::DistanceCache,
+    domain::DomainOp,
+    heightmap::Heightmap,
+    light::Light,
+    mesh::{Mesh, MeshSdf},
+    na,
+    offline::OfflineRenderer,
+    Camera, Projection, SettingsUniform,
+};
+
+const SIZE: (u32, u32) = (96, 64);
+// pixelmatch's threshold of 0.1 on its squared YIQ difference
+const THRESHOLD: f32 = 35215.0 * 0.1 * 0.1;
+// share of pixels allowed past the threshold, for small differences between software renderers
+const MAX_DIFFERENT: f32 = 0.005;
+
+struct Case {
+    name: &'static str,
+    scene: fn(&mut SettingsUniform),
+    time: f32,
+    camera: Camera,
+    // the default sun when not set
+    lights: Option<Vec<Light>>,
+    // lights the scene instead of the sky
+    environment: Option<image::Rgb32FImage>,
+    // shapes the terrain instead of noise
+    heightmap: Option<Heightmap>,
+    // added to the scene
+    mesh: Option<MeshSdf>,
+    // a coarse distance cache baked around the camera
+    distance_cache: bool,
+}
+impl Default for Case {
+    fn default() -> Self {
+        Self {
+            name: "",
+            scene: |_| {},
+            time: 0.0,
+            camera: Camera::default(),
+            lights: None,
+            environment: None,
+            heightmap: None,
+            mesh: None,
+            distance_cache: false,
+        }
+    }
+}
+
+fn looking_at(pos: na::Point3<f32>, target: na::Point3<f32>) -> Camera {
+    Camera {
+        pos,
+        rot: na::UnitQuaternion::face_towards(&(target - pos), &na::Vector3::y()),
+        ..Default::default()
+    }
+}
+
+// a sky fading to white at the horizon, a bright sun and a red strip over a brown ground
+fn environment() -> image::Rgb32FImage {
+    image::Rgb32FImage::from_fn(128, 64, |x, y| {
+        let (u, v) = (x as f32 / 128.0, y as f32 / 64.0);
+        if (u - 0.6).hypot(v - 0.3) < 0.03 {
+            image::Rgb([50.0, 45.0, 40.0])
+        } else if (v - 0.45).abs() < 0.03 && u < 0.3 {
+            image::Rgb([4.0, 0.2, 0.1])
+        } else if v > 0.5 {
+            image::Rgb([0.3, 0.25, 0.2])
+        } else {
+            image::Rgb([0.3 + v, 0.5 + v * 0.5, 1.0])
+        }
+    })
+}
+
+// a crater on a cone in the middle of flat ground
+fn heightmap() -> Heightmap {
+    let image = image::GrayImage::from_fn(64, 64, |x, y| {
+        let d = (x as f32 - 32.0).hypot(y as f32 - 32.0) / 32.0;
+        let h = (1.0 - d).max(0.0) * (d / 0.2).min(1.0);
+        image::Luma([(h * 255.0) as u8])
+    });
+    Heightmap::from_image(&image::DynamicImage::ImageLuma8(image))
+}
+
+// a tilted ring, coarse enough to show whether the grid is sampled in the right place
+fn mesh() -> MeshSdf {
+    let (segments, sides) = (24, 8);
+    let tilt = na::Rotation3::from_euler_angles(0.7, 0.0, 0.3);
+    let point = |i: u32, j: u32| {
+        let u = i as f32 / segments as f32 * std::f32::consts::TAU;
+        let v = j as f32 / sides as f32 * std::f32::consts::TAU;
+        let r = 1.0 + 0.35 * v.cos();
+        tilt * na::point![r * u.cos(), 0.35 * v.sin(), r * u.sin()]
+    };
+    let mut triangles = Vec::new();
+    for i in 0..segments {
+        for j in 0..sides {
+            let [a, b, c, d] = [
+                point(i, j),
+                point(i + 1, j),
+                point(i + 1, j + 1),
+                point(i, j + 1),
+            ];
+            triangles.extend([[a, b, c], [a, c, d]]);
+        }
+    }
+    MeshSdf::new(&Mesh { triangles }, 32)
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        Case {
+            name: "mandelbulb_front",
+            scene: SettingsUniform::set_mandelbulb_mut,
+            ..Default::default()
+        },
+        Case {
+            name: "mandelbulb_above",
+            scene: SettingsUniform::set_mandelbulb_mut,
+            time: 20.0,
+            camera: looking_at(na::point![1.5, 2.0, -1.5], na::Point3::origin()),
+            ..Default::default()
+        },
+        Case {
+            name: "mandelbulb_equirectangular",
+            scene: SettingsUniform::set_mandelbulb_mut,
+            camera: Camera {
+                projection: Projection::Equirectangular,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Case {
+            name: "sunset_equirectangular",
+            scene: |settings| {
+                settings.set_mandelbulb_mut();
+                settings.sun_dir = na::Vector3::new(1.0, 0.05, 0.3).normalize().into();
+            },
+            camera: Camera {
+                projection: Projection::Equirectangular,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Case {
+            name: "clouds_equirectangular",
+            scene: |settings| {
+                settings.set_mandelbulb_mut();
+                settings.sun_dir = na::Vector3::new(0.3, 0.7, 0.4).normalize().into();
+                settings.cloud_cover = 0.5;
+            },
+            camera: Camera {
+                projection: Projection::Equirectangular,
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        Case {
+            name: "menger_sponge_front",
+            scene: SettingsUniform::set_mengersponge_mut,
+            ..Default::default()
+        },
+        Case {
+            name: "menger_sponge_corner",
+            scene: SettingsUniform::set_mengersponge_mut,
+            camera: looking_at(na::point![2.0, 2.0, -2.0], na::Point3::origin()),
+            ..Default::default()
+        },
+        Case {
+            name: "menger_sponge_fog",
+            scene: |settings| {
+                settings.set_mengersponge_mut();
+                settings.fog_density = 0.2;
+                settings.fog_samples = 16;
+            },
+            // from below looking up through the holes
+            camera: looking_at(na::point![1.0, -2.0, -1.5], na::Point3::origin()),
+            ..Default::default()
+        },
+        Case {
+            name: "mandelbulb_lights",
+            scene: SettingsUniform::set_mandelbulb_mut,
+            lights: Some(vec![
+                Light::point(na::point![-1.2, 0.8, -1.2], 5.0)
+                    .with_color([1.0, 0.3, 0.1])
+                    .with_intensity(2.0),
+                Light::spot(
+                    na::point![1.5, 0.0, -1.5],
+                    na::vector![-1.0, 0.0, 1.0],
+                    5.0,
+                    0.6,
+                )
+                .with_color([0.2, 0.5, 1.0])
+                .with_intensity(4.0),
+                Light::sphere(na::point![0.0, 1.5, -0.5], 0.3, 5.0).with_intensity(2.0),
+            ]),
+            ..Default::default()
+        },
+        Case {
+            name: "mandelbulb_environment",
+            scene: SettingsUniform::set_mandelbulb_mut,
+            environment: Some(environment()),
+            ..Default::default()
+        },
+        Case {
+            name: "mandelbulb_mirror",
+            scene: |settings| {
+                settings.set_mandelbulb_mut();
+                settings.bounces = 2;
+                settings.metallic = 1.0;
+            },
+            camera: looking_at(na::point![1.5, 0.5, -1.5], na::Point3::origin()),
+            ..Default::default()
+        },
+        Case {
+            name: "menger_sponge_glass",
+            scene: |settings| {
+                settings.set_mengersponge_mut();
+                settings.bounces = 4;
+                settings.transmission = 1.0;
+                settings.absorption = [0.1, 0.5, 1.0];
+            },
+            ..Default::default()
+        },
+        Case {
+            name: "mandelbulb_glow",
+            scene: |settings| {
+                settings.set_mandelbulb_mut();
+                // at night so only the glow and emission show
+                settings.sun_dir = [0.0, -1.0, 0.0];
+                settings.glow_intensity = 1.0;
+                settings.emission = 0.2;
+            },
+            lights: Some(Vec::new()),
+            ..Default::default()
+        },
+        Case {
+            name: "menger_sponge_repeat",
+            scene: |settings| {
+                settings.set_mengersponge_mut();
+                settings.max_dist = 30.0;
+                settings.set_domain(&[
+                    DomainOp::polar(5),
+                    DomainOp::repeat(na::vector![3.0, 0.0, 3.0]),
+                ]);
+            },
+            camera: looking_at(na::point![1.5, 1.2, -3.0], na::Point3::origin()),
+            ..Default::default()
+        },
+        Case {
+            name: "mandelbulb_twist",
+            scene: |settings| {
+                settings.set_mandelbulb_mut();
+                settings.set_domain(&[
+                    DomainOp::mirror(na::vector![0.0, 0.0, -1.0], 0.0),
+                    DomainOp::twist(0.5),
+                    DomainOp::displace(0.05, 6.0),
+                ]);
+            },
+            ..Default::default()
+        },
+        Case {
+            name: "terrain",
+            scene: SettingsUniform::set_terrain_mut,
+            camera: looking_at(na::point![0.0, 0.3, -4.0], na::point![0.0, -1.0, 0.0]),
+            ..Default::default()
+        },
+        Case {
+            name: "terrain_heightmap",
+            scene: |settings| {
+                settings.set_terrain_mut();
+                settings.terrain_scale = 6.0;
+            },
+            camera: looking_at(na::point![0.0, 0.3, -4.0], na::point![0.0, -1.0, 0.0]),
+            heightmap: Some(heightmap()),
+            ..Default::default()
+        },
+        Case {
+            name: "mandelbulb_mesh",
+            scene: |settings| {
+                settings.set_mandelbulb_mut();
+                settings.mesh_position = [0.6, 0.2, -1.4];
+                settings.mesh_scale = 0.4;
+            },
+            mesh: Some(mesh()),
+            ..Default::default()
+        },
+        Case {
+            name: "menger_sponge_distance_cache",
+            scene: SettingsUniform::set_mengersponge_mut,
+            camera: looking_at(na::point![1.5, 1.2, -2.5], na::Point3::origin()),
+            distance_cache: true,
+            ..Default::default()
+        },
+    ]
+}
+
+// squared YIQ difference like pixelmatch, which is closer to what people notice than rgb distance
+fn color_delta(a: &image::Rgba<u8>, b: &image::Rgba<u8>) -> f32 {
+    let yiq = |p: &image::Rgba<u8>| {
+        let [r, g, b] = [p[0], p[1], p[2]].map(|c| c as f32);
+        [
+            r * 0.298_895 + g * 0.586_622 + b * 0.114_482,
+            r * 0.595_978 - g * 0.274_176 - b * 0.321_802,
+            r * 0.211_470 - g * 0.522_617 + b * 0.311_147,
+        ]
+    };
+    let (a, b) = (yiq(a), yiq(b));
+    let [y, i, q] = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+    0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
+
+/// Returns an image with the differences in red if there are too many.
+fn compare(expected: &image::RgbaImage, actual: &image::RgbaImage) -> Option<image::RgbaImage> {
+    if expected.dimensions() != actual.dimensions() {
+        return Some(actual.clone());
+    }
+
+    let mut different = 0;
+    let diff = image::RgbaImage::from_fn(actual.width(), actual.height(), |x, y| {
+        let (e, a) = (expected.get_pixel(x, y), actual.get_pixel(x, y));
+        if color_delta(e, a) > THRESHOLD {
+            different += 1;
+            image::Rgba([255, 0, 0, 255])
+        } else {
+            // faded so the differences stand out
+            let luma = (e[0] as u32 + e[1] as u32 + e[2] as u32) / 3;
+            let c = (255 - (255 - luma) / 4) as u8;
+            image::Rgba([c, c, c, 255])
+        }
+    });
+
+    let allowed = (actual.width() * actual.height()) as f32 * MAX_DIFFERENT;
+    (different as f32 > allowed).then_some(diff)
+}
+
+#[test]
+fn golden_images() {
+    let Some(mut renderer) = pollster::block_on(OfflineRenderer::new_fallback(SIZE)) else {
+        eprintln!("no software adapter, skipping golden image tests");
+        return;
+    };
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+    let golden_dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let diff_dir = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+
+    let default_settings = *renderer.settings();
+    let default_lights = renderer.lights().to_vec();
+    let mut failures = Vec::new();
+    for case in cases() {
+        *renderer.lights_mut() = case.lights.unwrap_or_else(|| default_lights.clone());
+        renderer.set_environment(case.environment.as_ref());
+        renderer.set_heightmap(case.heightmap);
+        renderer.set_mesh(case.mesh);
+        let settings = renderer.settings_mut();
+        *settings = default_settings;
+        (case.scene)(settings);
+        settings.time = case.time;
+        let cache = case.distance_cache.then(|| {
+            let settings = renderer.settings();
+            DistanceCache::bake(
+                settings,
+                renderer.scene_data(),
+                case.camera.pos,
+                settings.max_dist,
+                32,
+            )
+        });
+        renderer.set_distance_cache(cache);
+        let actual = renderer.render(&case.camera, 1);
+
+        let path = golden_dir.join(format!("{}.png", case.name));
+        if update {
+            std::fs::create_dir_all(&golden_dir).unwrap();
+            actual.save(&path).unwrap();
+            continue;
+        }
+
+        let expected = match image::open(&path) {
+            Ok(expected) => expected.to_rgba8(),
+            Err(e) => {
+                failures.push(format!("{}: {e}", path.display()));
+                continue;
+            }
+        };
+        if let Some(diff) = compare(&expected, &actual) {
+            std::fs::create_dir_all(&diff_dir).unwrap();
+            let actual_path = diff_dir.join(format!("{}_actual.png", case.name));
+            let diff_path = diff_dir.join(format!("{}_diff.png", case.name));
+            actual.save(&actual_path).unwrap();
+            diff.save(&diff_path).unwrap();
+            failures.push(format!(
+                "{} doesn't match, see {} and {}",
+                case.name,
+                actual_path.display(),
+                diff_path.display()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
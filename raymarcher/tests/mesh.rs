@@ -0,0 +1,138 @@
+// Checks the voxelised distances against shapes whose distance is known exactly.
+
+use raymarcher::{
+    mesh::{Mesh, MeshSdf},
+    na,
+};
+
+const CUBE: &str = "
+v -1 -1 -1
+v 1 -1 -1
+v 1 1 -1
+v -1 1 -1
+v -1 -1 1
+v 1 -1 1
+v 1 1 1
+v -1 1 1
+f 1 4 3 2
+f 5 6 7 8
+f 1 2 6 5
+f 4 8 7 3
+f 1 5 8 4
+f 2 3 7 6
+";
+
+fn box_distance(p: na::Point3<f32>) -> f32 {
+    let q = p.coords.abs() - na::Vector3::repeat(1.0);
+    q.sup(&na::Vector3::zeros()).magnitude() + q.max().min(0.0)
+}
+
+// spread through and around the mesh, the same every run
+fn points() -> impl Iterator<Item = na::Point3<f32>> {
+    (0..2000).map(|i| {
+        let x = i as f32;
+        let wrap = |t: f32| (t.fract() * 2.0 - 1.0) * 2.5;
+        na::point![
+            wrap(x * 0.618_034),
+            wrap(x * 0.414_214),
+            wrap(x * 0.732_051)
+        ]
+    })
+}
+
+// 36 cells across the cube and 2 more on each side
+const RESOLUTION: u32 = 40;
+const CELL: f32 = 2.0 / 36.0;
+
+#[test]
+fn cube_distances() {
+    let sdf = MeshSdf::new(&Mesh::parse_obj(CUBE).unwrap(), RESOLUTION);
+    for p in points().filter(|p| p.coords.amax() < 1.0 + 2.0 * CELL) {
+        let (d, expected) = (sdf.distance(p), box_distance(p));
+        assert!(
+            (d - expected).abs() < CELL / 2.0,
+            "{d} at {p} should be {expected}"
+        );
+    }
+}
+
+#[test]
+fn distance_bounds_the_mesh_outside_the_grid() {
+    let sdf = MeshSdf::new(&Mesh::parse_obj(CUBE).unwrap(), RESOLUTION);
+    for p in points().chain(points().map(|p| p * 4.0)) {
+        let (d, expected) = (sdf.distance(p), box_distance(p));
+        // never far enough to step through the surface
+        assert!(d < expected + CELL / 2.0, "{d} at {p} is past {expected}");
+        assert!(d > 0.0 || expected < CELL, "{d} at {p} should be outside");
+    }
+}
+
+#[test]
+fn meshes_are_centred_and_scaled() {
+    let moved = CUBE
+        .lines()
+        .map(|line| match line.strip_prefix("v ") {
+            Some(coords) => {
+                let coords: Vec<f32> = coords.split(' ').map(|c| c.parse().unwrap()).collect();
+                format!(
+                    "v {} {} {}",
+                    coords[0] * 3.0 + 5.0,
+                    coords[1] * 3.0,
+                    coords[2] * 3.0
+                )
+            }
+            None => line.to_string(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    assert_eq!(
+        MeshSdf::new(&Mesh::parse_obj(&moved).unwrap(), 16),
+        MeshSdf::new(&Mesh::parse_obj(CUBE).unwrap(), 16)
+    );
+}
+
+#[test]
+fn obj_faces() {
+    let plain = Mesh::parse_obj("v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4").unwrap();
+    let fancy = Mesh::parse_obj(
+        "# comment\nv 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nvn 0 0 1\nvt 0 0\nf -4/1/1 -3//1 -2/1 -1",
+    )
+    .unwrap();
+    assert_eq!(plain.triangles.len(), 2);
+    assert_eq!(plain, fancy);
+
+    assert!(Mesh::parse_obj("v 0 0 0\nf 1 2 3").is_err());
+    assert!(Mesh::parse_obj("v 0 zero 0").is_err());
+}
+
+#[test]
+fn stl_formats() {
+    let ascii = "solid triangle
+  facet normal 0 0 1
+    outer loop
+      vertex 0 0 0
+      vertex 1 0 0
+      vertex 0 1 0
+    endloop
+  endfacet
+endsolid triangle";
+
+    let mut binary = vec![0; 80];
+    binary.extend(1_u32.to_le_bytes());
+    for v in [
+        0.0_f32, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0,
+    ] {
+        binary.extend(v.to_le_bytes());
+    }
+    binary.extend([0, 0]);
+
+    let expected = Mesh {
+        triangles: vec![[
+            na::point![0.0, 0.0, 0.0],
+            na::point![1.0, 0.0, 0.0],
+            na::point![0.0, 1.0, 0.0],
+        ]],
+    };
+    assert_eq!(Mesh::parse_stl(ascii.as_bytes()).unwrap(), expected);
+    assert_eq!(Mesh::parse_stl(&binary).unwrap(), expected);
+}
@@ -0,0 +1,182 @@
+// Evaluates the shader's noise on a software adapter and checks it against noise.rs,
+// so things placed by the CPU line up with what's drawn.
+
+use raymarcher::{na, noise, noise::Noise, wgpu_context};
+
+const SAMPLES: u32 = 64;
+const TOLERANCE: f32 = 1e-4;
+
+const SHADER: &str = r#"
+@vertex
+fn vs_main(@builtin(vertex_index) i: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((i << 1u) & 2u), f32(i & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+
+// same as point() below
+fn point(x: f32) -> vec3<f32> {
+    return vec3<f32>(x * 0.37 - 11.3, x * 0.61 + 5.2, 2.9 - x * 0.23);
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4<f32>) -> @location(0) vec4<f32> {
+    let p = point(floor(position.x));
+    var v = 0.0;
+    switch u32(position.y) {
+        case 0u { v = value_noise2(p.xy); }
+        case 1u { v = value_noise3(p); }
+        case 2u { v = perlin_noise2(p.xy); }
+        case 3u { v = perlin_noise3(p); }
+        case 4u { v = simplex_noise2(p.xy); }
+        case 5u { v = simplex_noise3(p); }
+        case 6u { v = worley_noise2(p.xy); }
+        case 7u { v = worley_noise3(p); }
+        case 8u { v = fbm2(NOISE_PERLIN, p.xy, 5u, 2.0, 0.5); }
+        default { v = fbm3(NOISE_SIMPLEX, p, 5u, 2.0, 0.5); }
+    }
+    return vec4<f32>(v, 0.0, 0.0, 1.0);
+}
+"#;
+
+fn point(x: f32) -> na::Vector3<f32> {
+    na::vector![x * 0.37 - 11.3, x * 0.61 + 5.2, 2.9 - x * 0.23]
+}
+
+type NoiseFn = fn(na::Vector3<f32>) -> f32;
+
+// in the same order as the rows in the shader
+fn cpu_noise() -> Vec<(&'static str, NoiseFn)> {
+    vec![
+        ("value2", |p| noise::value2(p.xy())),
+        ("value3", noise::value3),
+        ("perlin2", |p| noise::perlin2(p.xy())),
+        ("perlin3", noise::perlin3),
+        ("simplex2", |p| noise::simplex2(p.xy())),
+        ("simplex3", noise::simplex3),
+        ("worley2", |p| noise::worley2(p.xy())),
+        ("worley3", noise::worley3),
+        ("fbm2", |p| noise::fbm2(Noise::Perlin, p.xy(), 5, 2.0, 0.5)),
+        ("fbm3", |p| noise::fbm3(Noise::Simplex, p, 5, 2.0, 0.5)),
+    ]
+}
+
+fn gpu_noise(device: &wgpu::Device, queue: &wgpu::Queue, rows: u32) -> Vec<f32> {
+    let source = format!("{}{SHADER}", include_str!("../src/pipeline/noise.wgsl"));
+    let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("Noise Test Shader"),
+        source: wgpu::ShaderSource::Wgsl(source.into()),
+    });
+    let format = wgpu::TextureFormat::R32Float;
+    let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some("Noise Test Pipeline"),
+        layout: None,
+        vertex: wgpu::VertexState {
+            module: &module,
+            entry_point: "vs_main",
+            buffers: &[],
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: &module,
+            entry_point: "fs_main",
+            targets: &[Some(format.into())],
+        }),
+        primitive: Default::default(),
+        depth_stencil: None,
+        multisample: Default::default(),
+        multiview: None,
+    });
+
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Noise Test Texture"),
+        size: wgpu::Extent3d {
+            width: SAMPLES,
+            height: rows,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&Default::default());
+
+    // rows of a buffer copy have to be aligned
+    let bytes_per_row = (SAMPLES * 4).div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT)
+        * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Noise Test Buffer"),
+        size: (bytes_per_row * rows) as wgpu::BufferAddress,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = device.create_command_encoder(&Default::default());
+    {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Noise Test Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &view,
+                resolve_target: None,
+                ops: Default::default(),
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+        pass.set_pipeline(&pipeline);
+        pass.draw(0..3, 0..1);
+    }
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &buffer,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(bytes_per_row),
+                rows_per_image: None,
+            },
+        },
+        texture.size(),
+    );
+    queue.submit(std::iter::once(encoder.finish()));
+
+    let slice = buffer.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |result| result.unwrap());
+    device.poll(wgpu::Maintain::Wait);
+    let data = slice.get_mapped_range();
+    data.chunks(bytes_per_row as usize)
+        .flat_map(|row| bytemuck::cast_slice::<u8, f32>(&row[..SAMPLES as usize * 4]))
+        .copied()
+        .collect()
+}
+
+#[test]
+fn noise_matches_shader() {
+    let instance = wgpu_context::instance();
+    let Some((_, device, queue)) = pollster::block_on(wgpu_context::request_fallback_device(
+        &instance,
+        wgpu::Features::empty(),
+    )) else {
+        eprintln!("no software adapter, skipping noise tests");
+        return;
+    };
+
+    let cpu = cpu_noise();
+    let gpu = gpu_noise(&device, &queue, cpu.len() as u32);
+
+    let mut failures = Vec::new();
+    for (row, (name, f)) in cpu.iter().enumerate() {
+        for x in 0..SAMPLES {
+            let expected = f(point(x as f32));
+            let actual = gpu[row * SAMPLES as usize + x as usize];
+            if (expected - actual).abs() > TOLERANCE {
+                failures.push(format!(
+                    "{name} at {x}: {expected} on the cpu, {actual} in the shader"
+                ));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
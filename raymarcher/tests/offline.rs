@@ -0,0 +1,20 @@
+// Checks that offline renders don't depend on what was rendered before them.
+
+use raymarcher::{offline::OfflineRenderer, Camera};
+
+#[test]
+fn rendering_twice_gives_the_same_image() {
+    let Some(mut renderer) = pollster::block_on(OfflineRenderer::new_fallback((48, 32))) else {
+        eprintln!("no software adapter, skipping");
+        return;
+    };
+    // out of focus so every sample lands somewhere else on the lens
+    let camera = Camera {
+        aperture: 0.3,
+        focus_dist: 1.0,
+        ..Default::default()
+    };
+    let first = renderer.render(&camera, 2);
+    let second = renderer.render(&camera, 2);
+    assert!(first == second);
+}